@@ -0,0 +1,122 @@
+use crate::{BBDD, Error, Result};
+
+/// 番剧的播放地区，用于选择 `BBDDBuilder::area_host` 配置的解析服务器
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Area {
+    /// 港澳
+    Hk,
+    /// 台湾
+    Tw,
+    /// 泰国等东南亚地区
+    Th,
+}
+
+impl Area {
+    pub const ALL: [Area; 3] = [Area::Hk, Area::Tw, Area::Th];
+
+    /// 作为 area 参数传给解析服务器
+    pub fn as_str(&self) -> &str {
+        match self {
+            Area::Hk => "hk",
+            Area::Tw => "tw",
+            Area::Th => "th",
+        }
+    }
+
+    pub fn parse(area: &str) -> Result<Area> {
+        Area::ALL
+            .into_iter()
+            .find(|a| a.as_str().eq_ignore_ascii_case(area.trim()))
+            .ok_or_else(|| Error::ParamError(format!("地区只能是 hk、tw 或 th: {}", area)))
+    }
+}
+
+impl BBDD {
+    /// 依次尝试的解析服务器，`BBDDBuilder::area` 指定的地区排在最前
+    pub fn area_hosts(&self) -> Vec<(Area, &str)> {
+        let mut hosts: Vec<(Area, &str)> = self
+            .area_hosts
+            .iter()
+            .map(|(area, host)| (*area, host.as_str()))
+            .collect();
+        if let Some(area) = self.area {
+            hosts.sort_by_key(|(a, _)| *a != area);
+        }
+        hosts
+    }
+
+    /// 先请求主站接口，遇到地区限制时依次通过配置的解析服务器重试；
+    /// 指定了地区时直接使用该地区的解析服务器。
+    /// request 的参数为 None 时请求主站，否则为解析服务器的地址和地区。
+    pub(crate) async fn with_area_fallback<T, F, Fut>(&self, request: F) -> Result<T>
+    where
+        F: Fn(Option<(Area, String)>) -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let hosts = self.area_hosts();
+        let preferred = self
+            .area
+            .is_some_and(|area| hosts.first().is_some_and(|(a, _)| *a == area));
+        let mut last_err = if preferred {
+            None
+        } else {
+            match request(None).await {
+                Err(e) if e.is_region_blocked() && !hosts.is_empty() => Some(e),
+                result => return result,
+            }
+        };
+        for (area, host) in hosts {
+            tracing::warn!("通过 {} 地区的解析服务器 {} 重试", area.as_str(), host);
+            match request(Some((area, host.to_string()))).await {
+                Err(e) => last_err = Some(e),
+                result => return result,
+            }
+        }
+        Err(last_err.unwrap_or_else(|| Error::StateError("没有可用的解析服务器".to_string())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Area;
+
+    #[test]
+    fn test_area_hosts_order() {
+        assert_eq!(Area::parse("TW").unwrap(), Area::Tw);
+        assert!(Area::parse("jp").is_err());
+        let bbdd = crate::BBDD::builder()
+            .area_host(Area::Hk, "https://hk.example.com/")
+            .area_host(Area::Tw, "https://tw.example.com")
+            .area(Area::Tw)
+            .build()
+            .unwrap();
+        assert_eq!(
+            bbdd.area_hosts(),
+            vec![
+                (Area::Tw, "https://tw.example.com"),
+                (Area::Hk, "https://hk.example.com")
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_area_fallback() {
+        let bbdd = crate::BBDD::builder()
+            .area_host(Area::Hk, "https://hk.example.com")
+            .build()
+            .unwrap();
+        let result = bbdd
+            .with_area_fallback(|area| async move {
+                match area {
+                    None => Err(crate::Error::ApiError {
+                        code: -10403,
+                        message: "抱歉您所在地区不可观看！".to_string(),
+                    }),
+                    Some((area, host)) => Ok(format!("{}@{}", area.as_str(), host)),
+                }
+            })
+            .await
+            .unwrap();
+        assert_eq!(result, "hk@https://hk.example.com");
+    }
+}
@@ -0,0 +1,210 @@
+use crate::{BBDD, Error, Result};
+use serde::{Deserialize, Serialize};
+
+const SONG_INFO_URL: &str = "https://www.bilibili.com/audio/music-service-c/web/song/info";
+
+const SONG_LYRIC_URL: &str = "https://www.bilibili.com/audio/music-service-c/web/song/lyric";
+
+const SONG_URL: &str = "https://api.bilibili.com/audio/music-service-c/url";
+
+const MENU_INFO_URL: &str = "https://www.bilibili.com/audio/music-service-c/web/menu/info";
+
+const MENU_SONGS_URL: &str = "https://www.bilibili.com/audio/music-service-c/web/song/of-menu";
+
+/// 音频音质，数值越大音质越高
+pub const AUDIO_QUALITY_128K: i64 = 0;
+pub const AUDIO_QUALITY_192K: i64 = 1;
+pub const AUDIO_QUALITY_320K: i64 = 2;
+pub const AUDIO_QUALITY_FLAC: i64 = 3;
+
+/// 获取歌单时每页的歌曲数
+const MENU_PAGE_SIZE: i64 = 100;
+
+impl BBDD {
+    /// 获取单曲信息，sid 为 au 号
+    pub async fn fetch_audio_info(&self, sid: i64) -> Result<AudioSongInfo> {
+        self.get_data(SONG_INFO_URL, Some(serde_json::json!({ "sid": sid })))
+            .await
+    }
+
+    /// 获取 LRC 格式的歌词，没有歌词时返回空字符串
+    pub async fn fetch_audio_lyric(&self, sid: i64) -> Result<String> {
+        let lyric: Option<String> = self
+            .get_data(SONG_LYRIC_URL, Some(serde_json::json!({ "sid": sid })))
+            .await?;
+        Ok(lyric.unwrap_or_default())
+    }
+
+    /// 获取音频流地址，quality 为期望的音质，服务器会返回账号可用的最高音质（不超过 quality）
+    pub async fn audio_stream_url(&self, sid: i64, quality: i64) -> Result<AudioStreamUrl> {
+        let mid = self
+            .cookie
+            .read()
+            .unwrap()
+            .get("DedeUserID")
+            .map(|c| c.value.clone())
+            .unwrap_or_else(|| "0".to_string());
+        let url: AudioStreamUrl = self
+            .get_data(
+                SONG_URL,
+                Some(serde_json::json!({
+                    "songid": sid,
+                    "quality": quality,
+                    "privilege": 2,
+                    "mid": mid,
+                    "platform": "android",
+                })),
+            )
+            .await?;
+        if url.cdns.is_empty() {
+            return Err(Error::StateError("没有可用的音频地址".to_string()));
+        }
+        Ok(url)
+    }
+
+    /// 获取歌单信息，sid 为 am 号
+    pub async fn fetch_audio_menu_info(&self, sid: i64) -> Result<AudioMenuInfo> {
+        self.get_data(MENU_INFO_URL, Some(serde_json::json!({ "sid": sid })))
+            .await
+    }
+
+    /// 获取歌单中的全部歌曲
+    pub async fn fetch_audio_menu_songs(&self, sid: i64) -> Result<Vec<AudioSongInfo>> {
+        let mut songs = vec![];
+        let mut page = 1;
+        loop {
+            let data: AudioSongPage = self
+                .get_data(
+                    MENU_SONGS_URL,
+                    Some(serde_json::json!({ "sid": sid, "pn": page, "ps": MENU_PAGE_SIZE })),
+                )
+                .await?;
+            songs.extend(data.data);
+            if page >= data.page_count {
+                break;
+            }
+            page += 1;
+        }
+        Ok(songs)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct AudioSongInfo {
+    pub id: i64,
+    #[serde(default)]
+    pub uid: i64,
+    #[serde(default)]
+    pub uname: String,
+    /// 歌手
+    #[serde(default)]
+    pub author: String,
+    #[serde(default)]
+    pub title: String,
+    #[serde(default)]
+    pub cover: String,
+    #[serde(default)]
+    pub intro: String,
+    /// 歌词文件地址
+    #[serde(default)]
+    pub lyric: String,
+    /// 时长（秒）
+    #[serde(default)]
+    pub duration: i64,
+    /// 发布时间（秒）
+    #[serde(default)]
+    pub passtime: i64,
+    /// 关联的视频
+    #[serde(default)]
+    pub aid: i64,
+    #[serde(default)]
+    pub bvid: String,
+    #[serde(default)]
+    pub cid: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct AudioStreamUrl {
+    pub sid: i64,
+    /// 实际返回的音质，见 `AUDIO_QUALITY_*`
+    #[serde(rename = "type")]
+    pub quality: i64,
+    #[serde(default)]
+    pub size: i64,
+    /// 第一个为主地址，其余为备用地址
+    #[serde(default)]
+    pub cdns: Vec<String>,
+    #[serde(default)]
+    pub qualities: Vec<AudioQuality>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct AudioQuality {
+    #[serde(rename = "type")]
+    pub quality: i64,
+    #[serde(default)]
+    pub desc: String,
+    #[serde(default)]
+    pub size: i64,
+    #[serde(default)]
+    pub bps: String,
+}
+
+impl AudioStreamUrl {
+    /// 音频文件的扩展名，无损为 flac，其余为 m4a
+    pub fn extension(&self) -> &str {
+        if self.quality == AUDIO_QUALITY_FLAC {
+            "flac"
+        } else {
+            "m4a"
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct AudioMenuInfo {
+    #[serde(rename = "menuId")]
+    pub menu_id: i64,
+    #[serde(default)]
+    pub uid: i64,
+    #[serde(default)]
+    pub uname: String,
+    #[serde(default)]
+    pub title: String,
+    #[serde(default)]
+    pub cover: String,
+    #[serde(default)]
+    pub intro: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+struct AudioSongPage {
+    #[serde(rename = "pageCount", default)]
+    page_count: i64,
+    #[serde(default)]
+    data: Vec<AudioSongInfo>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AUDIO_QUALITY_FLAC, AudioStreamUrl};
+    use crate::client::WebResponseData;
+
+    #[test]
+    fn test_audio_stream_url() {
+        let response: WebResponseData = serde_json::from_str(
+            r#"{"code":0,"msg":"success","data":{"sid":15664,"type":3,"info":"","timeout":10800,"size":31233402,
+            "cdns":["https://upos-sz-mirrorkodo.bilivideo.com/ugaxcode/a.flac","https://upos-sz-mirrorcos.bilivideo.com/ugaxcode/a.flac"],
+            "qualities":[{"type":2,"desc":"320K","size":9384123,"bps":"320kbit/s","tag":"","require":0,"requiredesc":""},
+            {"type":3,"desc":"无损 FLAC 音质","size":31233402,"bps":"FLAC","tag":"","require":1,"requiredesc":"大会员专享"}],
+            "title":"title","cover":""}}"#,
+        )
+        .unwrap();
+        assert_eq!(response.message, "success");
+        let url: AudioStreamUrl = serde_json::from_value(response.data).unwrap();
+        assert_eq!(url.quality, AUDIO_QUALITY_FLAC);
+        assert_eq!(url.extension(), "flac");
+        assert_eq!(url.cdns.len(), 2);
+        assert_eq!(url.qualities[1].desc, "无损 FLAC 音质");
+    }
+}
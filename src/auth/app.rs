@@ -0,0 +1,100 @@
+pub use crate::BBDD;
+pub use crate::error::Result;
+
+/// APP 接口签名使用的 appkey 和 appsec
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AppKey {
+    pub appkey: &'static str,
+    pub appsec: &'static str,
+}
+
+/// 云视听小电视，TV 扫码登录使用
+pub const TV_APP_KEY: AppKey = AppKey {
+    appkey: "4409e2ce8ffd12b8",
+    appsec: "59b43e04ad6965f34319062b478f83dd",
+};
+
+/// 补充 appkey 和 ts 后按参数名排序签名，返回带 sign 的查询字符串
+pub fn app_sign(params: &[(String, String)], app: &AppKey) -> String {
+    let mut params = params.to_vec();
+    if !params.iter().any(|(k, _)| k == "appkey") {
+        params.push(("appkey".to_string(), app.appkey.to_string()));
+    }
+    if !params.iter().any(|(k, _)| k == "ts") {
+        params.push(("ts".to_string(), chrono::Utc::now().timestamp().to_string()));
+    }
+    params.sort_by(|a, b| a.0.cmp(&b.0));
+    let mut url = reqwest::Url::parse("https://app.bilibili.com/").unwrap();
+    url.query_pairs_mut().extend_pairs(params.iter());
+    let query = url.query().unwrap_or_default().to_string();
+    let sign = hex::encode(md5::compute(format!("{}{}", query, app.appsec)).0);
+    format!("{}&sign={}", query, sign)
+}
+
+impl BBDD {
+    /// 签名 APP 接口参数，设置了 access_key 时自动附加
+    pub fn app_signed_query(&self, params: &[(String, String)], app: &AppKey) -> String {
+        let mut params = params.to_vec();
+        if let Some(access_key) = self.access_key()
+            && !params.iter().any(|(k, _)| k == "access_key")
+        {
+            params.push(("access_key".to_string(), access_key));
+        }
+        app_sign(&params, app)
+    }
+
+    /// 使用 APP 签名的 GET 请求
+    pub async fn get_app_data<T: for<'de> serde::Deserialize<'de>>(
+        &self,
+        url: &str,
+        params: &[(String, String)],
+        app: &AppKey,
+    ) -> Result<T> {
+        let url = format!("{}?{}", url, self.app_signed_query(params, app));
+        self.take_data(self.request(reqwest::Method::GET, url.as_str(), None, None))
+            .await
+    }
+
+    /// 使用 APP 签名的表单 POST 请求
+    pub async fn post_app_data<T: for<'de> serde::Deserialize<'de>>(
+        &self,
+        url: &str,
+        params: &[(String, String)],
+        app: &AppKey,
+    ) -> Result<T> {
+        let body = self.app_signed_query(params, app);
+        self.take_data(
+            self.request(reqwest::Method::POST, url, None, None)
+                .header(
+                    reqwest::header::CONTENT_TYPE,
+                    "application/x-www-form-urlencoded",
+                )
+                .body(body),
+        )
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AppKey, app_sign};
+
+    #[test]
+    fn test_app_sign() {
+        let app = AppKey {
+            appkey: "1d8b6e7d45233436",
+            appsec: "560c52ccd288fed045859ed18bffd973",
+        };
+        let params = [
+            ("id", "114514"),
+            ("str", "1919810"),
+            ("test", "いいよ，こいよ"),
+            ("ts", "1702204169"),
+        ]
+        .map(|(k, v)| (k.to_string(), v.to_string()));
+        assert_eq!(
+            app_sign(&params, &app),
+            "appkey=1d8b6e7d45233436&id=114514&str=1919810&test=%E3%81%84%E3%81%84%E3%82%88%EF%BC%8C%E3%81%93%E3%81%84%E3%82%88&ts=1702204169&sign=d54317b2dea8f9df3a14f02aeddc2b20"
+        );
+    }
+}
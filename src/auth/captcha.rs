@@ -0,0 +1,72 @@
+const CAPTCHA_URL: &str = "https://passport.bilibili.com/x/passport-login/captcha?source=main_web";
+
+pub use crate::BBDD;
+pub use crate::error::{Error, Result};
+
+pub use serde::{Deserialize, Serialize};
+
+use std::future::Future;
+
+impl BBDD {
+    /// 获取短信、密码登录前需要完成的极验验证码
+    pub async fn web_captcha(&self) -> Result<WebCaptchaData> {
+        self.get_data(CAPTCHA_URL, None).await
+    }
+
+    /// 获取验证码并交给 solver 完成
+    pub async fn web_solve_captcha(&self, solver: &impl CaptchaSolver) -> Result<CaptchaResult> {
+        let captcha = self.web_captcha().await?;
+        if captcha.captcha_type != "geetest" {
+            return Err(Error::StateError(format!(
+                "不支持的验证码类型: {}",
+                captcha.captcha_type
+            )));
+        }
+        solver.solve(&captcha).await
+    }
+}
+
+/// 由调用方完成极验验证，例如在 GUI 中嵌入极验页面，或者提示用户使用第三方工具完成验证
+pub trait CaptchaSolver {
+    fn solve(&self, captcha: &WebCaptchaData)
+    -> impl Future<Output = Result<CaptchaResult>> + Send;
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct WebCaptchaData {
+    #[serde(rename = "type")]
+    pub captcha_type: String,
+    pub token: String,
+    #[serde(default)]
+    pub geetest: WebCaptchaGeetest,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct WebCaptchaGeetest {
+    #[serde(default)]
+    pub gt: String,
+    #[serde(default)]
+    pub challenge: String,
+}
+
+/// 完成验证后提交给登录接口的参数
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct CaptchaResult {
+    /// WebCaptchaData 中的 token
+    pub token: String,
+    pub challenge: String,
+    pub validate: String,
+    pub seccode: String,
+}
+
+impl CaptchaResult {
+    /// seccode 为 `{validate}|jordan`
+    pub fn new(captcha: &WebCaptchaData, challenge: &str, validate: &str) -> CaptchaResult {
+        CaptchaResult {
+            token: captcha.token.clone(),
+            challenge: challenge.to_string(),
+            validate: validate.to_string(),
+            seccode: format!("{}|jordan", validate),
+        }
+    }
+}
@@ -1 +1,4 @@
+pub mod app;
+pub mod captcha;
+pub mod tv;
 pub mod web;
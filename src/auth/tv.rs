@@ -0,0 +1,127 @@
+const AUTH_CODE_URL: &str = "https://passport.bilibili.com/x/passport-tv-login/qrcode/auth_code";
+
+const POLL_URL: &str = "https://passport.bilibili.com/x/passport-tv-login/qrcode/poll";
+
+pub use crate::BBDD;
+pub use crate::error::{Error, Result};
+
+pub use serde::{Deserialize, Serialize};
+
+use super::app::TV_APP_KEY;
+use crate::cookie::{Cookie, CookieJar, DEFAULT_COOKIE_DOMAIN};
+
+/// 二维码未确认
+pub const TV_QR_CODE_WAITING: i32 = 86039;
+/// 二维码已扫码未确认
+pub const TV_QR_CODE_SCANNED: i32 = 86090;
+/// 二维码已失效
+pub const TV_QR_CODE_EXPIRED: i32 = 86038;
+
+impl BBDD {
+    /// 获取 TV 端扫码登录的二维码
+    pub async fn tv_login_qr_url(&self) -> Result<TvLoginQRData> {
+        self.post_app_data(
+            AUTH_CODE_URL,
+            &[("local_id".to_string(), "0".to_string())],
+            &TV_APP_KEY,
+        )
+        .await
+    }
+
+    /// 查询扫码结果，未完成时返回 code 为 TV_QR_CODE_* 的 ApiError，
+    /// 成功后会设置 access_key 并写入返回的 web cookie
+    pub async fn tv_login_qr_verify(&self, auth_code: &str) -> Result<TvLoginData> {
+        let data: TvLoginData = self
+            .post_app_data(
+                POLL_URL,
+                &[
+                    ("auth_code".to_string(), auth_code.to_string()),
+                    ("local_id".to_string(), "0".to_string()),
+                ],
+                &TV_APP_KEY,
+            )
+            .await?;
+        self.set_access_key(Some(data.access_token.clone()));
+        if let Some(jar) = data.cookie_jar() {
+            self.set_cookie_jar(jar)?;
+        }
+        Ok(data)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct TvLoginQRData {
+    pub url: String,
+    pub auth_code: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct TvLoginData {
+    #[serde(default)]
+    pub mid: i64,
+    /// 即 access_key
+    pub access_token: String,
+    #[serde(default)]
+    pub refresh_token: String,
+    /// 有效期（秒）
+    #[serde(default)]
+    pub expires_in: i64,
+    #[serde(default)]
+    pub cookie_info: Option<TvCookieInfo>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct TvCookieInfo {
+    #[serde(default)]
+    pub cookies: Vec<TvCookie>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct TvCookie {
+    pub name: String,
+    pub value: String,
+    #[serde(default)]
+    pub http_only: i32,
+    /// unix 秒
+    #[serde(default)]
+    pub expires: i64,
+}
+
+impl TvLoginData {
+    /// 登录结果中同时返回的 web cookie
+    pub fn cookie_jar(&self) -> Option<CookieJar> {
+        let info = self.cookie_info.as_ref()?;
+        if info.cookies.is_empty() {
+            return None;
+        }
+        let mut jar = CookieJar::default();
+        for c in &info.cookies {
+            let mut cookie = Cookie::new(c.name.as_str(), c.value.as_str(), DEFAULT_COOKIE_DOMAIN);
+            cookie.http_only = c.http_only == 1;
+            cookie.expires = Some(c.expires).filter(|e| *e > 0);
+            jar.insert(cookie);
+        }
+        Some(jar)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TvLoginData;
+
+    #[test]
+    fn test_tv_login_cookie_jar() {
+        let data: TvLoginData = serde_json::from_str(
+            r#"{"mid":1,"access_token":"token","refresh_token":"refresh","expires_in":15552000,
+            "cookie_info":{"cookies":[{"name":"SESSDATA","value":"s","http_only":1,"expires":4102444800},
+            {"name":"bili_jct","value":"j","http_only":0,"expires":4102444800}],"domains":[".bilibili.com"]}}"#,
+        )
+        .unwrap();
+        let jar = data.cookie_jar().unwrap();
+        assert!(jar.get("SESSDATA").unwrap().http_only);
+        assert_eq!(
+            jar.cookie_header("https://api.bilibili.com/"),
+            "SESSDATA=s; bili_jct=j"
+        );
+    }
+}
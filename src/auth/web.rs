@@ -5,11 +5,47 @@ const VERIFY_URL: &'static str = "https://passport.bilibili.com/x/passport-login
 
 const NAV_URL: &'static str = "https://api.bilibili.com/x/web-interface/nav";
 
+const COOKIE_INFO_URL: &str = "https://passport.bilibili.com/x/passport-login/web/cookie/info";
+
+const CORRESPOND_URL: &str = "https://www.bilibili.com/correspond/1/{correspondPath}";
+
+const COOKIE_REFRESH_URL: &str =
+    "https://passport.bilibili.com/x/passport-login/web/cookie/refresh";
+
+const CONFIRM_REFRESH_URL: &str =
+    "https://passport.bilibili.com/x/passport-login/web/confirm/refresh";
+
+const SMS_SEND_URL: &str = "https://passport.bilibili.com/x/passport-login/web/sms/send";
+
+const SMS_LOGIN_URL: &str = "https://passport.bilibili.com/x/passport-login/web/login/sms";
+
+const LOGIN_KEY_URL: &str = "https://passport.bilibili.com/x/passport-login/web/key";
+
+const PASSWORD_LOGIN_URL: &str = "https://passport.bilibili.com/x/passport-login/web/login";
+
+const LOGOUT_URL: &str = "https://passport.bilibili.com/login/exit/v2";
+
+// 用于生成 correspondPath 的公钥
+const CORRESPOND_PUBLIC_KEY: &str = "-----BEGIN PUBLIC KEY-----
+MIGfMA0GCSqGSIb3DQEBAQUAA4GNADCBiQKBgQDLgd2OAkcGVtoE3ThUREbio0Eg
+Uc/prcajMKXvkCKFCWhJYJcLkcM2DKKcSeFpD/j6Boy538YXnR6VhcuUJOhH2x71
+nzPjfdTcqMz7djHum0qSZA0AyCBDABUqCrfNgCiJ00Ra7GmRj+YCK1NJEuewlb40
+JNrRuoEUXpabUzGB8QIDAQAB
+-----END PUBLIC KEY-----";
+
 pub use crate::BBDD;
 pub use crate::error::{Error, Result};
 
 pub use serde::{Deserialize, Serialize};
 
+use super::captcha::{CaptchaResult, CaptchaSolver};
+use base64::Engine;
+#[cfg(feature = "stream")]
+use futures::Stream;
+use rsa::pkcs8::DecodePublicKey;
+#[cfg(feature = "stream")]
+use std::time::Duration;
+
 impl BBDD {
     pub async fn web_nav(&self) -> Result<WebNavData> {
         self.get_data(NAV_URL, None).await
@@ -28,6 +64,368 @@ impl BBDD {
         let url = VERIFY_URL.replace("{qrcodeKey}", qrcode_key);
         self.get_data(&url, None).await
     }
+
+    /// 查询一次扫码状态
+    pub async fn web_login_qr_poll(&self, qrcode_key: &str) -> Result<WebLoginQRState> {
+        self.web_login_qr_verify(qrcode_key).await?.state()
+    }
+
+    /// 生成二维码并每隔 interval 查询一次扫码状态，只在状态变化时产生新的值。
+    /// 二维码失效后产生 Expired 并自动重新生成二维码，登录成功后结束；
+    /// 生成二维码失败时产生错误并结束，查询失败时产生错误并继续查询。需要启用 stream feature
+    #[cfg(feature = "stream")]
+    pub fn web_login_qr_stream(
+        &self,
+        interval: Duration,
+    ) -> impl Stream<Item = Result<WebLoginQRState>> + '_ {
+        futures::stream::unfold(QrStreamStep::Generate, move |step| async move {
+            match step {
+                QrStreamStep::Generate => match self.web_login_qr_url().await {
+                    Ok(qr) => {
+                        let next = QrStreamStep::Poll {
+                            qrcode_key: qr.qrcode_key.clone(),
+                            last: None,
+                        };
+                        Some((Ok(WebLoginQRState::Generated(qr)), next))
+                    }
+                    Err(e) => Some((Err(e), QrStreamStep::Done)),
+                },
+                QrStreamStep::Poll { qrcode_key, last } => loop {
+                    tokio::time::sleep(interval).await;
+                    match self.web_login_qr_poll(&qrcode_key).await {
+                        Ok(state) if last.as_ref() == Some(&state) => continue,
+                        Ok(WebLoginQRState::Expired) => {
+                            return Some((Ok(WebLoginQRState::Expired), QrStreamStep::Generate));
+                        }
+                        Ok(state @ WebLoginQRState::Success(_)) => {
+                            return Some((Ok(state), QrStreamStep::Done));
+                        }
+                        Ok(state) => {
+                            let next = QrStreamStep::Poll {
+                                qrcode_key,
+                                last: Some(state.clone()),
+                            };
+                            return Some((Ok(state), next));
+                        }
+                        Err(e) => return Some((Err(e), QrStreamStep::Poll { qrcode_key, last })),
+                    }
+                },
+                QrStreamStep::Done => None,
+            }
+        })
+    }
+
+    /// 发送短信验证码，cid 为国际区号（中国大陆为 86），返回登录时需要的 captcha_key
+    pub async fn web_sms_send(
+        &self,
+        cid: i64,
+        tel: &str,
+        captcha: &CaptchaResult,
+    ) -> Result<String> {
+        let data: WebSmsSendData = self
+            .post_form_data(
+                SMS_SEND_URL,
+                serde_json::json!({
+                    "cid": cid,
+                    "tel": tel,
+                    "source": "main_web",
+                    "token": captcha.token,
+                    "challenge": captcha.challenge,
+                    "validate": captcha.validate,
+                    "seccode": captcha.seccode,
+                }),
+            )
+            .await?;
+        Ok(data.captcha_key)
+    }
+
+    /// 获取验证码并交给 solver 完成后发送短信验证码
+    pub async fn web_sms_send_with(
+        &self,
+        cid: i64,
+        tel: &str,
+        solver: &impl CaptchaSolver,
+    ) -> Result<String> {
+        let captcha = self.web_solve_captcha(solver).await?;
+        self.web_sms_send(cid, tel, &captcha).await
+    }
+
+    /// 使用短信验证码登录，成功后 cookie 会通过 Set-Cookie 写入
+    pub async fn web_login_sms(
+        &self,
+        cid: i64,
+        tel: &str,
+        code: &str,
+        captcha_key: &str,
+    ) -> Result<WebLoginData> {
+        let data: WebLoginData = self
+            .post_form_data(
+                SMS_LOGIN_URL,
+                serde_json::json!({
+                    "cid": cid,
+                    "tel": tel,
+                    "code": code,
+                    "source": "main_mini",
+                    "captcha_key": captcha_key,
+                    "go_url": "https://www.bilibili.com/",
+                }),
+            )
+            .await?;
+        data.check()
+    }
+
+    /// 获取密码登录使用的公钥和盐
+    pub async fn web_login_key(&self) -> Result<WebLoginKeyData> {
+        self.get_data(LOGIN_KEY_URL, None).await
+    }
+
+    /// 使用账号密码登录，密码会先使用 web_login_key 返回的公钥加密，成功后 cookie 会通过 Set-Cookie 写入
+    pub async fn web_login_password(
+        &self,
+        username: &str,
+        password: &str,
+        captcha: &CaptchaResult,
+    ) -> Result<WebLoginData> {
+        let key = self.web_login_key().await?;
+        let data: WebLoginData = self
+            .post_form_data(
+                PASSWORD_LOGIN_URL,
+                serde_json::json!({
+                    "username": username,
+                    "password": encrypt_password(&key, password)?,
+                    "keep": 0,
+                    "source": "main_web",
+                    "token": captcha.token,
+                    "challenge": captcha.challenge,
+                    "validate": captcha.validate,
+                    "seccode": captcha.seccode,
+                    "go_url": "https://www.bilibili.com/",
+                }),
+            )
+            .await?;
+        data.check()
+    }
+
+    /// 获取验证码并交给 solver 完成后使用账号密码登录
+    pub async fn web_login_password_with(
+        &self,
+        username: &str,
+        password: &str,
+        solver: &impl CaptchaSolver,
+    ) -> Result<WebLoginData> {
+        let captcha = self.web_solve_captcha(solver).await?;
+        self.web_login_password(username, password, &captcha).await
+    }
+
+    /// 检查当前 cookie 是否需要刷新
+    pub async fn web_cookie_info(&self) -> Result<WebCookieInfoData> {
+        self.get_data(
+            COOKIE_INFO_URL,
+            Some(serde_json::json!({ "csrf": self.csrf() })),
+        )
+        .await
+    }
+
+    /// 访问 correspond 页面取得刷新 cookie 所需的 refresh_csrf，timestamp 为毫秒
+    pub async fn web_refresh_csrf(&self, timestamp: i64) -> Result<String> {
+        let url = CORRESPOND_URL.replace("{correspondPath}", &correspond_path(timestamp)?);
+        let html = self.get_web_source(&url).await?;
+        extract_refresh_csrf(&html)
+            .ok_or_else(|| Error::StateError("correspond 页面中未找到 refresh_csrf".to_string()))
+    }
+
+    /// 使用登录时保存的 refresh_token 刷新 cookie，新的 cookie 会通过 Set-Cookie 写入，
+    /// 返回新的 refresh_token，调用方需要保存它以便下次刷新
+    pub async fn web_refresh_cookie(&self, refresh_token: &str) -> Result<String> {
+        let info = self.web_cookie_info().await?;
+        self.refresh_cookie_at(refresh_token, info.timestamp).await
+    }
+
+    /// timestamp 为 cookie/info 返回的毫秒时间戳，不大于 0 时使用当前时间
+    async fn refresh_cookie_at(&self, refresh_token: &str, timestamp: i64) -> Result<String> {
+        let timestamp = if timestamp > 0 {
+            timestamp
+        } else {
+            chrono::Utc::now().timestamp_millis()
+        };
+        let refresh_csrf = self.web_refresh_csrf(timestamp).await?;
+        let refresh: WebCookieRefreshData = self
+            .post_form_data(
+                COOKIE_REFRESH_URL,
+                serde_json::json!({
+                    "csrf": self.csrf(),
+                    "refresh_csrf": refresh_csrf,
+                    "source": "main_web",
+                    "refresh_token": refresh_token,
+                }),
+            )
+            .await?;
+        // 确认刷新时使用新的 bili_jct 和旧的 refresh_token，使旧的 refresh_token 失效
+        let _: serde_json::Value = self
+            .post_form_data(
+                CONFIRM_REFRESH_URL,
+                serde_json::json!({
+                    "csrf": self.csrf(),
+                    "refresh_token": refresh_token,
+                }),
+            )
+            .await?;
+        Ok(refresh.refresh_token)
+    }
+
+    /// 需要时刷新 cookie，未刷新时返回 None
+    pub async fn web_refresh_cookie_if_needed(
+        &self,
+        refresh_token: &str,
+    ) -> Result<Option<String>> {
+        if refresh_token.is_empty() {
+            return Ok(None);
+        }
+        // 不再通过 web_refresh_cookie 重复请求 cookie/info，cookie 已失效时它会再次返回未登录
+        let Some(timestamp) = refresh_timestamp(self.web_cookie_info().await)? else {
+            return Ok(None);
+        };
+        self.refresh_cookie_at(refresh_token, timestamp)
+            .await
+            .map(Some)
+    }
+
+    /// 退出登录，使服务端的会话失效，成功后清空本地 cookie（包括 `persist_cookies` 设置的文件）
+    pub async fn web_logout(&self) -> Result<()> {
+        let csrf = self.csrf();
+        if csrf.is_empty() {
+            return Err(Error::StateError("缺少 bili_jct，无法退出登录".to_string()));
+        }
+        let _: serde_json::Value = self
+            .post_form_data(LOGOUT_URL, serde_json::json!({ "biliCSRF": csrf }))
+            .await?;
+        self.set_cookie_jar(crate::CookieJar::default())
+    }
+
+    pub(crate) fn csrf(&self) -> String {
+        self.cookie
+            .read()
+            .unwrap()
+            .get("bili_jct")
+            .map(|c| c.value.clone())
+            .unwrap_or_default()
+    }
+}
+
+/// 根据 cookie/info 的结果判断是否需要刷新，需要时返回刷新使用的时间戳；
+/// 账号未登录（cookie 已失效）时同样需要刷新，此时没有时间戳，返回 0
+fn refresh_timestamp(info: Result<WebCookieInfoData>) -> Result<Option<i64>> {
+    match info {
+        Ok(info) => Ok(info.refresh.then_some(info.timestamp)),
+        Err(e) if e.is_login_required() => Ok(Some(0)),
+        Err(e) => Err(e),
+    }
+}
+
+/// 使用固定公钥以 RSA-OAEP(SHA256) 加密 `refresh_{timestamp}`，返回十六进制字符串
+pub fn correspond_path(timestamp: i64) -> Result<String> {
+    let key = rsa::RsaPublicKey::from_public_key_pem(CORRESPOND_PUBLIC_KEY)
+        .map_err(|e| Error::StateError(format!("Invalid correspond public key: {}", e)))?;
+    let encrypted = key
+        .encrypt(
+            &mut rsa::rand_core::OsRng,
+            rsa::Oaep::new::<sha2::Sha256>(),
+            format!("refresh_{}", timestamp).as_bytes(),
+        )
+        .map_err(|e| Error::StateError(format!("Failed to encrypt correspond path: {}", e)))?;
+    Ok(hex::encode(encrypted))
+}
+
+/// 使用 RSA(PKCS#1 v1.5) 加密盐和密码，返回 base64 字符串
+pub fn encrypt_password(key: &WebLoginKeyData, password: &str) -> Result<String> {
+    let public_key = rsa::RsaPublicKey::from_public_key_pem(key.key.trim())
+        .map_err(|e| Error::StateError(format!("Invalid login public key: {}", e)))?;
+    let encrypted = public_key
+        .encrypt(
+            &mut rsa::rand_core::OsRng,
+            rsa::Pkcs1v15Encrypt,
+            format!("{}{}", key.hash, password).as_bytes(),
+        )
+        .map_err(|e| Error::StateError(format!("Failed to encrypt password: {}", e)))?;
+    Ok(base64::engine::general_purpose::STANDARD.encode(encrypted))
+}
+
+fn extract_refresh_csrf(html: &str) -> Option<String> {
+    let re = regex::Regex::new(r#"<div id="1-name">\s*([^<\s]+)\s*</div>"#).ok()?;
+    re.captures(html)
+        .and_then(|caps| caps.get(1))
+        .map(|m| m.as_str().to_string())
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct WebSmsSendData {
+    pub captcha_key: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct WebLoginKeyData {
+    /// 盐，加密时拼接在密码前面
+    pub hash: String,
+    /// PEM 格式的公钥
+    pub key: String,
+}
+
+/// 短信、密码登录的结果，字段与扫码登录成功时相同
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct WebLoginData {
+    /// 0 为登录成功，其它值需要到 url 中进行额外的验证
+    #[serde(default)]
+    pub status: i32,
+    #[serde(default)]
+    pub message: String,
+    #[serde(default)]
+    pub url: String,
+    #[serde(default)]
+    pub refresh_token: String,
+    #[serde(default)]
+    pub timestamp: i64,
+}
+
+impl WebLoginData {
+    fn check(self) -> Result<WebLoginData> {
+        if self.status != 0 {
+            return Err(Error::StateError(format!(
+                "登录需要进一步验证 ({}): {} {}",
+                self.status, self.message, self.url
+            )));
+        }
+        Ok(self)
+    }
+}
+
+impl From<WebLoginData> for WebLoginQRVerifyData {
+    fn from(data: WebLoginData) -> Self {
+        WebLoginQRVerifyData {
+            url: data.url,
+            refresh_token: data.refresh_token,
+            timestamp: data.timestamp,
+            code: 0,
+            message: data.message,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct WebCookieInfoData {
+    #[serde(default)]
+    pub refresh: bool,
+    /// 毫秒
+    #[serde(default)]
+    pub timestamp: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct WebCookieRefreshData {
+    #[serde(default)]
+    pub status: i32,
+    #[serde(default)]
+    pub message: String,
+    pub refresh_token: String,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
@@ -45,6 +443,51 @@ pub struct WebLoginQRVerifyData {
     pub message: String,
 }
 
+const QR_CODE_WAITING: i32 = 86101;
+const QR_CODE_SCANNED: i32 = 86090;
+const QR_CODE_EXPIRED: i32 = 86038;
+
+impl WebLoginQRVerifyData {
+    /// 未知的状态码作为 ApiError 返回
+    pub fn state(self) -> Result<WebLoginQRState> {
+        match self.code {
+            0 => Ok(WebLoginQRState::Success(self)),
+            QR_CODE_WAITING => Ok(WebLoginQRState::Waiting),
+            QR_CODE_SCANNED => Ok(WebLoginQRState::Scanned),
+            QR_CODE_EXPIRED => Ok(WebLoginQRState::Expired),
+            code => Err(Error::ApiError {
+                code,
+                message: self.message,
+            }),
+        }
+    }
+}
+
+/// 扫码登录的状态
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WebLoginQRState {
+    /// 生成了新的二维码，需要展示给用户
+    Generated(WebLoginQRData),
+    /// 未扫码
+    Waiting,
+    /// 已扫码未确认
+    Scanned,
+    /// 二维码已失效
+    Expired,
+    /// 登录成功，cookie 可以通过 `store_login` 或 `CookieJar::from_cross_domain_url` 保存
+    Success(WebLoginQRVerifyData),
+}
+
+#[cfg(feature = "stream")]
+enum QrStreamStep {
+    Generate,
+    Poll {
+        qrcode_key: String,
+        last: Option<WebLoginQRState>,
+    },
+    Done,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
 pub struct WebNavWbiImg {
     #[serde(default)]
@@ -89,28 +532,103 @@ pub struct WebNavData {
 
 #[cfg(test)]
 mod tests {
+    use super::{
+        WebCookieInfoData, WebLoginKeyData, WebLoginQRState, WebLoginQRVerifyData, correspond_path,
+        encrypt_password, extract_refresh_csrf, refresh_timestamp,
+    };
+
+    #[test]
+    fn test_refresh_timestamp() {
+        let info = |refresh| WebCookieInfoData {
+            refresh,
+            timestamp: 1684466082386,
+        };
+        assert_eq!(refresh_timestamp(Ok(info(false))).unwrap(), None);
+        assert_eq!(
+            refresh_timestamp(Ok(info(true))).unwrap(),
+            Some(1684466082386)
+        );
+        // cookie 已过期时 cookie/info 返回未登录，仍然需要刷新
+        let expired = crate::Error::ApiError {
+            code: -101,
+            message: "账号未登录".to_string(),
+        };
+        assert_eq!(refresh_timestamp(Err(expired)).unwrap(), Some(0));
+        let banned = crate::Error::ApiError {
+            code: -102,
+            message: "账号被封停".to_string(),
+        };
+        assert!(refresh_timestamp(Err(banned)).is_err());
+    }
+
+    #[test]
+    fn test_correspond_path() {
+        let path = correspond_path(1684466082386).unwrap();
+        // 1024 位公钥，密文为 128 字节
+        assert_eq!(path.len(), 256);
+        assert_ne!(path, correspond_path(1684466082386).unwrap());
+    }
 
+    #[test]
+    fn test_encrypt_password() {
+        let key = WebLoginKeyData {
+            hash: "c7e2d1b8a3e5f6d4".to_string(),
+            key: super::CORRESPOND_PUBLIC_KEY.to_string(),
+        };
+        let encrypted = encrypt_password(&key, "password").unwrap();
+        // 1024 位公钥，密文为 128 字节
+        assert_eq!(encrypted.len(), 172);
+        assert!(encrypt_password(&WebLoginKeyData::default(), "password").is_err());
+    }
+
+    #[test]
+    fn test_extract_refresh_csrf() {
+        let html =
+            r#"<div id="1-name">b0cc8411ded2f9db2cff2edb3123acac</div><div id="2-name"></div>"#;
+        assert_eq!(
+            extract_refresh_csrf(html).as_deref(),
+            Some("b0cc8411ded2f9db2cff2edb3123acac")
+        );
+        assert_eq!(extract_refresh_csrf("<html></html>"), None);
+    }
+
+    #[test]
+    fn test_qr_verify_state() {
+        let verify = |code: i32| WebLoginQRVerifyData {
+            code,
+            ..Default::default()
+        };
+        assert_eq!(verify(86101).state().unwrap(), WebLoginQRState::Waiting);
+        assert_eq!(verify(86090).state().unwrap(), WebLoginQRState::Scanned);
+        assert_eq!(verify(86038).state().unwrap(), WebLoginQRState::Expired);
+        assert_eq!(
+            verify(0).state().unwrap(),
+            WebLoginQRState::Success(verify(0))
+        );
+        assert!(verify(1).state().is_err());
+    }
+
+    #[cfg(feature = "stream")]
     #[tokio::test]
     async fn test_web_log_qr_url() {
+        use futures::StreamExt;
         crate::tests::log_init();
-        let url = crate::tests::BBDD.web_login_qr_url().await.unwrap();
-        println!("{:?}", url);
-        qr2term::print_qr(url.url.as_str()).unwrap();
-        loop {
-            tokio::time::sleep(std::time::Duration::from_secs(3)).await;
-            let verify = crate::tests::BBDD
-                .web_login_qr_verify(&url.qrcode_key)
-                .await
-                .unwrap();
-            if verify.code == 86101 || verify.code == 86090 {
-                continue;
-            }
-            if verify.code == 0 {
-                println!("Login success: {:?}", verify);
-                crate::tests::store_login(&verify).unwrap();
-                break;
+        let stream = crate::tests::BBDD.web_login_qr_stream(std::time::Duration::from_secs(3));
+        futures::pin_mut!(stream);
+        while let Some(state) = stream.next().await {
+            match state.unwrap() {
+                WebLoginQRState::Generated(url) => {
+                    println!("{:?}", url);
+                    qr2term::print_qr(url.url.as_str()).unwrap();
+                }
+                WebLoginQRState::Success(verify) => {
+                    println!("Login success: {:?}", verify);
+                    crate::tests::store_login(&verify).unwrap();
+                }
+                // 二维码失效后流会重新生成二维码，测试在第一次失效时结束
+                WebLoginQRState::Expired => panic!("二维码已失效"),
+                state => println!("{:?}", state),
             }
-            panic!("Login failed: {:?}", verify);
         }
     }
 }
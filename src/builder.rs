@@ -0,0 +1,465 @@
+use crate::area::Area;
+use crate::cookie::{CookieJar, DEFAULT_COOKIE_DOMAIN};
+use crate::scheduler::{RateLimitConfig, Scheduler};
+use crate::{BBDD, Error, Result};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+
+/// 代理配置，支持 http / https / socks5 / socks5h 代理
+///
+/// ```
+/// let proxy = bbdd::ProxyConfig::parse("http://127.0.0.1:7890")
+///     .unwrap()
+///     .basic_auth("user", "pass")
+///     .no_proxy(["localhost", "127.0.0.1"]);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProxyConfig {
+    pub url: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub no_proxy: Vec<String>,
+}
+
+const PROXY_SCHEMES: [&str; 6] = ["http", "https", "socks4", "socks4a", "socks5", "socks5h"];
+
+impl ProxyConfig {
+    pub fn parse(url: &str) -> Result<ProxyConfig> {
+        let url = url.trim();
+        let scheme = url
+            .split_once("://")
+            .map(|(scheme, _)| scheme.to_ascii_lowercase())
+            .ok_or_else(|| Error::ParamError(format!("代理地址缺少协议: {}", url)))?;
+        if !PROXY_SCHEMES.contains(&scheme.as_str()) {
+            return Err(Error::ParamError(format!(
+                "不支持的代理协议: {}，可用的协议: {}",
+                scheme,
+                PROXY_SCHEMES.join(", ")
+            )));
+        }
+        if scheme.starts_with("socks") && !cfg!(feature = "socks") {
+            return Err(Error::ParamError(
+                "使用 socks 代理需要启用 socks feature".to_string(),
+            ));
+        }
+        reqwest::Proxy::all(url)?;
+        Ok(ProxyConfig {
+            url: url.to_string(),
+            ..Default::default()
+        })
+    }
+
+    pub fn basic_auth(mut self, username: impl Into<String>, password: impl Into<String>) -> Self {
+        self.username = Some(username.into());
+        self.password = Some(password.into());
+        self
+    }
+
+    /// 不经过代理的主机列表，格式与 NO_PROXY 环境变量相同
+    pub fn no_proxy<I, S>(mut self, hosts: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.no_proxy = hosts.into_iter().map(Into::into).collect();
+        self
+    }
+
+    fn to_reqwest(&self) -> Result<reqwest::Proxy> {
+        let mut proxy = reqwest::Proxy::all(self.url.as_str())?;
+        if let Some(username) = &self.username {
+            proxy = proxy.basic_auth(username, self.password.as_deref().unwrap_or_default());
+        }
+        if !self.no_proxy.is_empty() {
+            proxy = proxy.no_proxy(reqwest::NoProxy::from_string(&self.no_proxy.join(",")));
+        }
+        Ok(proxy)
+    }
+}
+
+/// TLS 实现，可选项取决于启用的 native-tls / rustls feature
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TlsBackend {
+    /// 使用 reqwest 默认的 TLS 实现
+    #[default]
+    Default,
+    #[cfg(feature = "native-tls")]
+    NativeTls,
+    #[cfg(feature = "rustls")]
+    Rustls,
+}
+
+/// HTTP 协议版本
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum HttpVersion {
+    /// 自动协商，启用 http3 feature 时默认直接使用 HTTP/3
+    #[default]
+    Default,
+    Http1Only,
+    #[cfg(feature = "http2")]
+    Http2PriorKnowledge,
+    #[cfg(feature = "http3")]
+    Http3PriorKnowledge,
+}
+
+/// cookie 来源
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CookieSource {
+    /// 直接使用的 cookie 字符串，例如 `SESSDATA=xxx; bili_jct=xxx`
+    Raw(String),
+    /// 扫码登录成功后返回的 crossDomain 链接
+    CrossDomainUrl(String),
+    /// 保存了 `WebLoginQRVerifyData` 的 json 文件，文件不存在时视为未登录
+    WebLoginFile(PathBuf),
+    /// 已经构造好的 cookie 集合
+    Jar(CookieJar),
+    /// 保存了 `CookieJar` 的 json 文件，文件不存在时视为未登录
+    JarFile(PathBuf),
+}
+
+impl CookieSource {
+    /// 读取 cookie，不发送请求
+    pub fn load(&self) -> Result<CookieJar> {
+        match self {
+            CookieSource::Raw(cookie) => Ok(CookieJar::parse_header(cookie, DEFAULT_COOKIE_DOMAIN)),
+            CookieSource::CrossDomainUrl(url) => CookieJar::from_cross_domain_url(url),
+            CookieSource::WebLoginFile(path) => match read_optional(path)? {
+                Some(json) => {
+                    let data: crate::auth::web::WebLoginQRVerifyData = serde_json::from_str(&json)?;
+                    CookieJar::from_cross_domain_url(&data.url)
+                }
+                None => Ok(CookieJar::default()),
+            },
+            CookieSource::Jar(jar) => Ok(jar.clone()),
+            CookieSource::JarFile(path) => match read_optional(path)? {
+                Some(json) => {
+                    let mut jar: CookieJar = serde_json::from_str(&json)?;
+                    jar.remove_expired();
+                    Ok(jar)
+                }
+                None => Ok(CookieJar::default()),
+            },
+        }
+    }
+}
+
+fn read_optional(path: &PathBuf) -> Result<Option<String>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    std::fs::read_to_string(path)
+        .map(Some)
+        .map_err(|e| Error::StateError(format!("无法读取登录信息 {}: {}", path.display(), e)))
+}
+
+/// BBDD 构建器
+///
+/// 构建出的客户端不会自动跟随重定向（`get_302_location` 依赖于此）。
+/// `proxy` 同时作用于接口请求和媒体下载，`api_proxy` / `media_proxy` 可以分别覆盖；
+/// `timeout` 只作用于接口请求，避免大文件下载被整体超时打断。
+///
+/// ```
+/// let bbdd = bbdd::BBDD::builder()
+///     .ua("Mozilla/5.0")
+///     .cookie("SESSDATA=xxx")
+///     .connect_timeout(std::time::Duration::from_secs(10))
+///     .header("Accept-Language", "zh-CN")
+///     .build()
+///     .unwrap();
+/// assert_eq!(bbdd.ua(), "Mozilla/5.0");
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct BBDDBuilder {
+    ua: Option<String>,
+    ua_file: Option<PathBuf>,
+    cookie: Option<CookieSource>,
+    persist_cookies: Option<PathBuf>,
+    access_key: Option<String>,
+    rate_limit: Option<RateLimitConfig>,
+    disable_fingerprint: bool,
+    area_hosts: Vec<(Area, String)>,
+    area: Option<Area>,
+    proxy: Option<ProxyConfig>,
+    api_proxy: Option<ProxyConfig>,
+    media_proxy: Option<ProxyConfig>,
+    timeout: Option<Duration>,
+    connect_timeout: Option<Duration>,
+    read_timeout: Option<Duration>,
+    tls_backend: TlsBackend,
+    http_version: HttpVersion,
+    headers: Vec<(String, String)>,
+}
+
+impl BBDD {
+    pub fn builder() -> BBDDBuilder {
+        BBDDBuilder::default()
+    }
+}
+
+impl BBDDBuilder {
+    /// 不设置时使用 `random_user_agent` 生成
+    pub fn ua(mut self, ua: impl Into<String>) -> Self {
+        self.ua = Some(ua.into());
+        self
+    }
+
+    /// 从文件读取 UA，文件不存在时生成随机 UA 并写入该文件，使多次运行的 UA 保持一致
+    pub fn ua_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.ua_file = Some(path.into());
+        self
+    }
+
+    pub fn cookie(mut self, cookie: impl Into<String>) -> Self {
+        self.cookie = Some(CookieSource::Raw(cookie.into()));
+        self
+    }
+
+    pub fn cookie_source(mut self, source: CookieSource) -> Self {
+        self.cookie = Some(source);
+        self
+    }
+
+    /// 将 cookie 保存到该文件，服务端通过 Set-Cookie 更新 cookie 后会自动重新写入
+    pub fn persist_cookies(mut self, path: impl Into<PathBuf>) -> Self {
+        self.persist_cookies = Some(path.into());
+        self
+    }
+
+    /// TV/APP 扫码登录得到的 access_key，用于 APP 签名的接口
+    pub fn access_key(mut self, access_key: impl Into<String>) -> Self {
+        self.access_key = Some(access_key.into());
+        self
+    }
+
+    /// 接口请求的限速和风控重试，不设置时使用 `RateLimitConfig::default()`
+    pub fn rate_limit(mut self, rate_limit: RateLimitConfig) -> Self {
+        self.rate_limit = Some(rate_limit);
+        self
+    }
+
+    /// 是否自动获取 buvid3、buvid4、b_nut、_uuid 和 bili_ticket 等指纹 cookie，默认开启
+    pub fn fingerprint(mut self, enabled: bool) -> Self {
+        self.disable_fingerprint = !enabled;
+        self
+    }
+
+    /// 地区限制番剧的解析服务器（自行部署的反向代理），例如 `https://hk.example.com`，
+    /// 主站接口返回地区限制时通过它重新获取播放地址
+    pub fn area_host(mut self, area: Area, host: impl Into<String>) -> Self {
+        let host = host.into().trim_end_matches('/').to_string();
+        self.area_hosts.retain(|(a, _)| *a != area);
+        self.area_hosts.push((area, host));
+        self
+    }
+
+    /// 直接使用该地区的解析服务器，不先请求主站
+    pub fn area(mut self, area: Area) -> Self {
+        self.area = Some(area);
+        self
+    }
+
+    pub fn proxy(mut self, proxy: ProxyConfig) -> Self {
+        self.proxy = Some(proxy);
+        self
+    }
+
+    /// 仅用于 api.bilibili.com 等接口请求的代理
+    pub fn api_proxy(mut self, proxy: ProxyConfig) -> Self {
+        self.api_proxy = Some(proxy);
+        self
+    }
+
+    /// 仅用于 CDN 媒体下载的代理
+    pub fn media_proxy(mut self, proxy: ProxyConfig) -> Self {
+        self.media_proxy = Some(proxy);
+        self
+    }
+
+    /// 接口请求的整体超时
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// 两次读取之间的超时，同时作用于媒体下载
+    pub fn read_timeout(mut self, timeout: Duration) -> Self {
+        self.read_timeout = Some(timeout);
+        self
+    }
+
+    pub fn tls_backend(mut self, tls_backend: TlsBackend) -> Self {
+        self.tls_backend = tls_backend;
+        self
+    }
+
+    pub fn http_version(mut self, http_version: HttpVersion) -> Self {
+        self.http_version = http_version;
+        self
+    }
+
+    /// 附加到每个请求上的请求头
+    pub fn header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    pub fn build(self) -> Result<BBDD> {
+        let api_proxy = self.api_proxy.as_ref().or(self.proxy.as_ref());
+        let media_proxy = self.media_proxy.as_ref().or(self.proxy.as_ref());
+        let agent = Arc::new(self.build_agent(api_proxy, self.timeout)?);
+        let media_agent = if api_proxy == media_proxy && self.timeout.is_none() {
+            agent.clone()
+        } else {
+            Arc::new(self.build_agent(media_proxy, None)?)
+        };
+        let ua = match (&self.ua, &self.ua_file) {
+            (Some(ua), _) => ua.clone(),
+            (None, Some(path)) => load_or_create_ua(path)?,
+            (None, None) => crate::util::random_user_agent(),
+        };
+        let cookie = match &self.cookie {
+            Some(source) => source.load()?,
+            None => CookieJar::default(),
+        };
+        let bbdd = BBDD {
+            agent,
+            media_agent,
+            ua,
+            cookie: RwLock::new(cookie),
+            cookie_file: self.persist_cookies.clone(),
+            access_key: RwLock::new(self.access_key.clone()),
+            scheduler: Scheduler::new(self.rate_limit.clone().unwrap_or_default()),
+            wbi_key: RwLock::new(None),
+            fingerprint: !self.disable_fingerprint,
+            fingerprint_attempt: Mutex::new(None),
+            area_hosts: self.area_hosts.clone(),
+            area: self.area,
+        };
+        // 从其他来源加载的 cookie 第一次写入持久化文件
+        if let Some(path) = &self.persist_cookies
+            && !path.exists()
+            && !bbdd.cookie_jar().is_empty()
+        {
+            bbdd.save_cookies()?;
+        }
+        Ok(bbdd)
+    }
+
+    fn build_agent(
+        &self,
+        proxy: Option<&ProxyConfig>,
+        timeout: Option<Duration>,
+    ) -> Result<reqwest::Client> {
+        let mut builder = reqwest::Client::builder().redirect(reqwest::redirect::Policy::none());
+        #[cfg(feature = "http2")]
+        {
+            builder = builder
+                .http2_adaptive_window(true)
+                .http2_keep_alive_interval(Some(Duration::from_secs(20)))
+                .http2_keep_alive_timeout(Duration::from_secs(20));
+        }
+        builder = match self.http_version {
+            #[cfg(feature = "http3")]
+            HttpVersion::Default | HttpVersion::Http3PriorKnowledge => {
+                builder.http3_prior_knowledge()
+            }
+            #[cfg(not(feature = "http3"))]
+            HttpVersion::Default => builder,
+            HttpVersion::Http1Only => builder.http1_only(),
+            #[cfg(feature = "http2")]
+            HttpVersion::Http2PriorKnowledge => builder.http2_prior_knowledge(),
+        };
+        builder = match self.tls_backend {
+            TlsBackend::Default => builder,
+            #[cfg(feature = "native-tls")]
+            TlsBackend::NativeTls => builder.tls_backend_native(),
+            #[cfg(feature = "rustls")]
+            TlsBackend::Rustls => builder.tls_backend_rustls(),
+        };
+        if let Some(proxy) = proxy {
+            builder = builder.proxy(proxy.to_reqwest()?);
+        }
+        if let Some(timeout) = timeout {
+            builder = builder.timeout(timeout);
+        }
+        if let Some(timeout) = self.connect_timeout {
+            builder = builder.connect_timeout(timeout);
+        }
+        if let Some(timeout) = self.read_timeout {
+            builder = builder.read_timeout(timeout);
+        }
+        if !self.headers.is_empty() {
+            let mut headers = reqwest::header::HeaderMap::new();
+            for (name, value) in &self.headers {
+                let name = reqwest::header::HeaderName::from_bytes(name.as_bytes())
+                    .map_err(|e| Error::ParamError(format!("请求头名称无效 {}: {}", name, e)))?;
+                let value = reqwest::header::HeaderValue::from_str(value)
+                    .map_err(|e| Error::ParamError(format!("请求头的值无效 {}: {}", value, e)))?;
+                headers.append(name, value);
+            }
+            builder = builder.default_headers(headers);
+        }
+        Ok(builder.build()?)
+    }
+}
+
+fn load_or_create_ua(path: &PathBuf) -> Result<String> {
+    if path.exists() {
+        std::fs::read_to_string(path)
+            .map(|ua| ua.trim().to_string())
+            .map_err(|e| Error::StateError(format!("无法读取UA {}: {}", path.display(), e)))
+    } else {
+        let ua = crate::util::random_user_agent();
+        std::fs::write(path, &ua)
+            .map_err(|e| Error::StateError(format!("无法写入UA {}: {}", path.display(), e)))?;
+        Ok(ua)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ProxyConfig;
+
+    #[test]
+    fn test_proxy_config_parse() {
+        assert!(ProxyConfig::parse("http://127.0.0.1:7890").is_ok());
+        assert!(ProxyConfig::parse("ftp://127.0.0.1:21").is_err());
+        assert!(ProxyConfig::parse("127.0.0.1:7890").is_err());
+    }
+
+    #[test]
+    fn test_build_with_separate_media_proxy() {
+        let bbdd = crate::BBDD::builder()
+            .api_proxy(ProxyConfig::parse("http://127.0.0.1:7890").unwrap())
+            .build()
+            .unwrap();
+        assert!(!std::sync::Arc::ptr_eq(&bbdd.agent, &bbdd.media_agent));
+        let bbdd = crate::BBDD::builder().build().unwrap();
+        assert!(std::sync::Arc::ptr_eq(&bbdd.agent, &bbdd.media_agent));
+    }
+
+    #[test]
+    fn test_build_with_invalid_header() {
+        let result = crate::BBDD::builder().header("Bad Header", "x").build();
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_cookie_source_missing_login_file() {
+        let bbdd = crate::BBDD::builder()
+            .cookie_source(super::CookieSource::WebLoginFile(
+                std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+                    .join("target")
+                    .join("not_exists_login.json"),
+            ))
+            .build()
+            .unwrap();
+        assert!(bbdd.cookie_jar().is_empty());
+    }
+}
@@ -1,15 +1,118 @@
+use crate::cookie::CookieJar;
+use crate::scheduler::Scheduler;
 use crate::{Error, Result};
 use serde::{Deserialize, Serialize};
-use std::sync::Arc;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Instant;
 use tracing::debug;
 
+/// 使用 `BBDD::builder()` 创建
 pub struct BBDD {
-    pub agent: Arc<reqwest::Client>,
-    pub ua: String,
-    pub cookie: String,
+    pub(crate) agent: Arc<reqwest::Client>,
+    /// 用于 CDN 媒体下载，未单独配置代理时与 agent 相同
+    pub(crate) media_agent: Arc<reqwest::Client>,
+    pub(crate) ua: String,
+    pub(crate) cookie: RwLock<CookieJar>,
+    /// 设置后 cookie 的变化会写入该文件
+    pub(crate) cookie_file: Option<PathBuf>,
+    /// APP 签名接口使用的 access_key
+    pub(crate) access_key: RwLock<Option<String>>,
+    pub(crate) scheduler: Scheduler,
+    pub(crate) wbi_key: RwLock<Option<crate::wbi::WbiKey>>,
+    /// 是否自动获取 buvid3 / bili_ticket 等指纹 cookie
+    pub(crate) fingerprint: bool,
+    /// 上一次获取指纹失败（或正在获取）的时间
+    pub(crate) fingerprint_attempt: Mutex<Option<Instant>>,
+    /// 地区限制番剧的解析服务器
+    pub(crate) area_hosts: Vec<(crate::area::Area, String)>,
+    pub(crate) area: Option<crate::area::Area>,
 }
 
 impl BBDD {
+    pub fn agent(&self) -> &Arc<reqwest::Client> {
+        &self.agent
+    }
+
+    pub fn media_agent(&self) -> &Arc<reqwest::Client> {
+        &self.media_agent
+    }
+
+    pub fn ua(&self) -> &str {
+        &self.ua
+    }
+
+    /// 发送到 www.bilibili.com 的 Cookie 请求头
+    pub fn cookie(&self) -> String {
+        self.cookie_jar().cookie_header("https://www.bilibili.com/")
+    }
+
+    pub fn cookie_jar(&self) -> CookieJar {
+        self.cookie.read().unwrap().clone()
+    }
+
+    /// 替换全部 cookie，例如重新登录之后
+    pub fn set_cookie_jar(&self, jar: CookieJar) -> Result<()> {
+        *self.cookie.write().unwrap() = jar;
+        self.save_cookies()
+    }
+
+    pub fn access_key(&self) -> Option<String> {
+        self.access_key.read().unwrap().clone()
+    }
+
+    pub fn set_access_key(&self, access_key: Option<String>) {
+        *self.access_key.write().unwrap() = access_key;
+    }
+
+    pub fn has_cookie(&self, name: &str) -> bool {
+        self.cookie.read().unwrap().get(name).is_some()
+    }
+
+    /// 将 cookie 写入 `persist_cookies` 设置的文件，未设置时什么也不做，cookie 为空时删除该文件
+    pub fn save_cookies(&self) -> Result<()> {
+        let Some(path) = &self.cookie_file else {
+            return Ok(());
+        };
+        if self.cookie.read().unwrap().is_empty() {
+            if path.exists() {
+                std::fs::remove_file(path).map_err(|e| {
+                    Error::StateError(format!("无法删除cookie文件 {}: {}", path.display(), e))
+                })?;
+            }
+            return Ok(());
+        }
+        let json = serde_json::to_string_pretty(&self.cookie_jar())?;
+        std::fs::write(path, json)
+            .map_err(|e| Error::StateError(format!("无法写入cookie文件 {}: {}", path.display(), e)))
+    }
+
+    /// 按 `rate_limit` 的配置限速后发送请求，并根据响应的 Set-Cookie 更新 cookie，
+    /// 发往 api.bilibili.com 的请求会补充缺少的指纹 cookie
+    pub async fn send(&self, request: reqwest::RequestBuilder) -> Result<reqwest::Response> {
+        let (client, request) = request.build_split();
+        let mut request = request?;
+        if let Some(host) = request.url().host_str().map(str::to_string) {
+            if self.fingerprint_before_send(host.as_str()).await {
+                self.append_fingerprint_cookies(&mut request);
+            }
+            self.scheduler.acquire(host.as_str()).await;
+        }
+        let response = client.execute(request).await?;
+        let changed = self
+            .cookie
+            .write()
+            .unwrap()
+            .update_from_response(response.url(), response.headers());
+        if changed {
+            debug!("Cookie updated by {}", response.url());
+            if let Err(e) = self.save_cookies() {
+                tracing::warn!("保存cookie失败: {}", e);
+            }
+        }
+        Ok(response)
+    }
+
     pub fn request(
         &self,
         method: reqwest::Method,
@@ -17,22 +120,28 @@ impl BBDD {
         query: Option<serde_json::Value>,
         body: Option<serde_json::Value>,
     ) -> reqwest::RequestBuilder {
-        let cookie = &self.cookie;
+        let cookie = if url.contains("/ep") || url.contains("/ss") {
+            self.cookie
+                .read()
+                .unwrap()
+                .cookie_header_with(url, &[("CURRENT_FNVAL", "4048")])
+        } else {
+            self.cookie.read().unwrap().cookie_header(url)
+        };
         let request = self
             .agent
             .request(method, url)
             .header("User-Agent", &self.ua)
-            .header("Accept-Encoding", "gzip, deflate")
-            .header(
-                "Cookie",
-                if url.contains("/ep") || url.contains("/ss") {
-                    format!("{};CURRENT_FNVAL=4048;", cookie)
-                } else {
-                    cookie.to_string()
-                },
-            );
+            .header("Accept-Encoding", "gzip, deflate");
+        let request = if cookie.is_empty() {
+            request
+        } else {
+            request.header("Cookie", cookie)
+        };
         let request = if url.contains("api.bilibili.com") {
             request.header("Referer", "https://www.bilibili.com/")
+        } else if url.contains("api.live.bilibili.com") {
+            request.header("Referer", "https://live.bilibili.com/")
         } else if url.contains("api.bilibili.tv") {
             request.header(
                 "sec-ch-ua",
@@ -49,15 +158,47 @@ impl BBDD {
         }
     }
 
+    /// 发送请求并读取响应内容，遇到 HTTP 412 或者 -412 / -352 等风控错误时退避重试
+    async fn take_text(&self, request: reqwest::RequestBuilder) -> Result<String> {
+        let mut attempt = 0;
+        let mut request = Some(request);
+        loop {
+            let current = request.take().unwrap();
+            // 保留一份用于重试，body 为流时无法复制，不进行重试
+            request = current.try_clone();
+            let response = self.send(current).await?;
+            let status = response.status();
+            let text = response.text().await?;
+            debug!("Response Status: {}", status);
+            debug!("Response Body: {}", text);
+            let rate_limited = status == reqwest::StatusCode::PRECONDITION_FAILED
+                || serde_json::from_str::<WebResponseCode>(&text).is_ok_and(|r| {
+                    crate::ApiErrorKind::from_code(r.code, "") == crate::ApiErrorKind::RateLimited
+                });
+            if !rate_limited {
+                return Ok(text);
+            }
+            if request.is_none() || attempt >= self.scheduler.config.max_retries {
+                if status == reqwest::StatusCode::PRECONDITION_FAILED {
+                    return Err(Error::ApiError {
+                        code: -412,
+                        message: "请求被拦截".to_string(),
+                    });
+                }
+                return Ok(text);
+            }
+            let delay = self.scheduler.backoff(attempt);
+            tracing::warn!("请求被风控拦截，{:?} 后重试", delay);
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+
     pub async fn take_data<T: for<'de> serde::Deserialize<'de>>(
         &self,
         request: reqwest::RequestBuilder,
     ) -> Result<T> {
-        let response = request.send().await?;
-        let code = response.status();
-        let text = response.text().await?;
-        debug!("Response Status: {}", code);
-        debug!("Response Body: {}", text);
+        let text = self.take_text(request).await?;
         let response: WebResponseData = serde_json::from_str(&text)?;
         if response.code != 0 {
             return Err(Error::ApiError {
@@ -72,11 +213,7 @@ impl BBDD {
         &self,
         request: reqwest::RequestBuilder,
     ) -> Result<T> {
-        let response = request.send().await?;
-        let code = response.status();
-        let text = response.text().await?;
-        debug!("Response Status: {}", code);
-        debug!("Response Body: {}", text);
+        let text = self.take_text(request).await?;
         let response: WebResponseResult = serde_json::from_str(&text)?;
         if response.code != 0 {
             return Err(Error::ApiError {
@@ -88,11 +225,7 @@ impl BBDD {
     }
 
     pub async fn take_json(&self, request: reqwest::RequestBuilder) -> Result<serde_json::Value> {
-        let response = request.send().await?;
-        let code = response.status();
-        let text = response.text().await?;
-        debug!("Response Status: {}", code);
-        debug!("Response Body: {}", text);
+        let text = self.take_text(request).await?;
         Ok(serde_json::from_str(&text)?)
     }
 
@@ -123,10 +256,22 @@ impl BBDD {
             .await
     }
 
+    /// 以 application/x-www-form-urlencoded 提交表单，并取出 data 字段
+    pub async fn post_form_data<T: for<'de> serde::Deserialize<'de>>(
+        &self,
+        url: &str,
+        form: serde_json::Value,
+    ) -> Result<T> {
+        self.take_data(
+            self.request(reqwest::Method::POST, url, None, None)
+                .form(&form),
+        )
+        .await
+    }
+
     pub async fn get_302_location(&self, url: &str) -> Result<Option<String>> {
         let response = self
-            .request(reqwest::Method::GET, url, None, None)
-            .send()
+            .send(self.request(reqwest::Method::GET, url, None, None))
             .await?;
         let code = response.status();
         let header = response.headers().clone();
@@ -144,20 +289,24 @@ impl BBDD {
     }
 
     pub async fn get_web_source(&self, url: &str) -> Result<String> {
-        let request = self.request(reqwest::Method::GET, url, None, None);
-        let response = request.send().await?;
-        let code = response.status();
-        let text = response.text().await?;
-        debug!("Response Status: {}", code);
-        debug!("Response Status: {}", code);
-        Ok(text)
+        self.take_text(self.request(reqwest::Method::GET, url, None, None))
+            .await
     }
 }
 
+/// 只用于判断是否为风控错误
+#[derive(Deserialize)]
+struct WebResponseCode {
+    code: i32,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
 pub struct WebResponseData {
     pub code: i32,
+    /// 音频区等接口使用 msg 字段
+    #[serde(default, alias = "msg")]
     pub message: String,
+    #[serde(default)]
     pub data: serde_json::Value,
     #[serde(default)]
     pub ttl: i32,
@@ -1,67 +1,157 @@
 use std::path::Path;
-use std::sync::Arc;
 use tokio::sync::OnceCell;
-#[cfg(feature = "http2")]
-use std::time::Duration;
 
-pub(crate) static CONFIG_DIR: OnceCell<String> = OnceCell::const_new();
+/// 当前账号的 cookie、UA 等文件所在目录
+pub(crate) static PROFILE_DIR: OnceCell<String> = OnceCell::const_new();
 pub(crate) static CLIENT_CELL: OnceCell<bbdd::BBDD> = OnceCell::const_new();
 
-pub(crate) async fn init_client(client_dir: String) {
-    CONFIG_DIR.get_or_init(|| async { client_dir }).await;
-    CLIENT_CELL.get_or_init(|| async { bbdd().await }).await;
+pub(crate) async fn init_client(profile_dir: String) {
+    PROFILE_DIR.get_or_init(|| async { profile_dir }).await;
+    CLIENT_CELL
+        .get_or_init(|| async { build_client(Path::new(PROFILE_DIR.get().unwrap())) })
+        .await;
 }
 
-async fn bbdd() -> bbdd::BBDD {
-    let ua = ua().await;
-    let builder = reqwest::Client::builder().redirect(reqwest::redirect::Policy::none());
-    #[cfg(feature = "http2")]
-    let builder = builder
-        .http2_adaptive_window(true)
-        .http2_keep_alive_interval(Some(Duration::from_secs(20)))
-        .http2_keep_alive_timeout(Duration::from_secs(20));
-    #[cfg(feature = "http3")]
-    let builder = builder.http3_prior_knowledge();
-    let agent = builder.build().unwrap();
-    let cookie = cookie().await;
-    bbdd::BBDD {
-        agent: Arc::new(agent),
-        ua,
-        cookie,
+/// 使用账号目录中的 cookie 和 UA 创建客户端
+pub(crate) fn build_client(profile_dir: &Path) -> bbdd::BBDD {
+    // cookies.json 保存服务端更新过的 cookie，不存在时从扫码登录的 web_login.json 迁移
+    let cookie_path = profile_dir.join("cookies.json");
+    let cookie_source = if cookie_path.exists() {
+        bbdd::CookieSource::JarFile(cookie_path.clone())
+    } else {
+        bbdd::CookieSource::WebLoginFile(profile_dir.join("web_login.json"))
+    };
+    let mut builder = bbdd::BBDD::builder()
+        .ua_file(profile_dir.join("ua.txt"))
+        .cookie_source(cookie_source)
+        .persist_cookies(cookie_path);
+    if let Some(tv_login) = load_tv_login(profile_dir) {
+        builder = builder.access_key(tv_login.access_token);
+    }
+    if let Some(config) = super::CONFIG.get() {
+        let proxy_config = |proxy: &Option<String>| {
+            config
+                .proxy_config(proxy)
+                .unwrap_or_else(|e| panic!("代理配置无效 : {}", super::format_bbdd_error(&e)))
+        };
+        if let Some(proxy) = proxy_config(&config.proxy) {
+            builder = builder.proxy(proxy);
+        }
+        if let Some(proxy) = proxy_config(&config.api_proxy) {
+            builder = builder.api_proxy(proxy);
+        }
+        if let Some(proxy) = proxy_config(&config.media_proxy) {
+            builder = builder.media_proxy(proxy);
+        }
+        for (area, host) in config.area_hosts() {
+            builder = builder.area_host(area, host);
+        }
+        if let Some(area) = &config.area {
+            builder = builder.area(bbdd::Area::parse(area).unwrap());
+        }
+    }
+    match builder.build() {
+        Ok(client) => client,
+        Err(e) => panic!("创建客户端失败 : {}", super::format_bbdd_error(&e)),
     }
 }
 
-async fn ua() -> String {
-    let ua_path = format!("{}/{}", CONFIG_DIR.get().unwrap(), "ua.txt");
-    let ua_path = Path::new(&ua_path);
-    if ua_path.exists() {
-        tokio::fs::read_to_string(ua_path)
-            .await
-            .expect(format!("无法读取配置文件 : {}", ua_path.display()).as_str())
-    } else {
-        let ua = bbdd::util::random_user_agent();
-        std::fs::write(ua_path, &ua).unwrap();
-        ua
-    }
+/// 保存登录信息并写入 cookie，登录返回的链接中没有可用的 cookie 时返回错误
+pub(crate) fn store_login(data: &bbdd::auth::web::WebLoginQRVerifyData) -> bbdd::BBDDResult<()> {
+    let jar = bbdd::CookieJar::from_cross_domain_url(&data.url).map_err(|e| {
+        bbdd::BBDDError::StateError(format!(
+            "登录返回的链接无效: {}",
+            super::format_bbdd_error(&e)
+        ))
+    })?;
+    let cookie_path = format!("{}/{}", PROFILE_DIR.get().unwrap(), "web_login.json");
+    std::fs::write(cookie_path.as_str(), serde_json::to_string_pretty(data).unwrap()).map_err(
+        |e| bbdd::BBDDError::StateError(format!("无法写入配置文件 {} : {}", cookie_path, e)),
+    )?;
+    CLIENT_CELL.get().unwrap().set_cookie_jar(jar)
 }
 
-async fn cookie() -> String {
-    let cookie_path = format!("{}/{}", CONFIG_DIR.get().unwrap(), "web_login.json");
-    let cookie_path = Path::new(&cookie_path);
-    if cookie_path.exists() {
-        let json = tokio::fs::read_to_string(cookie_path)
-            .await
-            .expect(format!("无法读取配置文件 : {}", cookie_path.display()).as_str());
-        let data: bbdd::auth::web::WebLoginQRVerifyData = serde_json::from_str(&json).unwrap();
-        bbdd::util::url_to_cookie(&data.url).unwrap()
-    } else {
-        String::new()
+/// TV 扫码登录保存的 access_key
+#[derive(serde::Serialize, serde::Deserialize)]
+pub(crate) struct TvLogin {
+    pub(crate) mid: i64,
+    pub(crate) access_token: String,
+    pub(crate) refresh_token: String,
+    /// unix 秒
+    pub(crate) expires_at: i64,
+}
+
+/// 读取未过期的 tv_login.json
+fn load_tv_login(profile_dir: &Path) -> Option<TvLogin> {
+    let text = std::fs::read_to_string(profile_dir.join("tv_login.json")).ok()?;
+    let tv_login: TvLogin = serde_json::from_str(&text).ok()?;
+    (tv_login.expires_at > chrono::Utc::now().timestamp()).then_some(tv_login)
+}
+
+pub(crate) fn store_tv_login(data: &bbdd::auth::tv::TvLoginData) {
+    let path = format!("{}/{}", PROFILE_DIR.get().unwrap(), "tv_login.json");
+    let tv_login = TvLogin {
+        mid: data.mid,
+        access_token: data.access_token.clone(),
+        refresh_token: data.refresh_token.clone(),
+        expires_at: chrono::Utc::now().timestamp() + data.expires_in,
+    };
+    std::fs::write(path.as_str(), serde_json::to_string_pretty(&tv_login).unwrap())
+        .unwrap_or_else(|_| panic!("无法写入配置文件 : {}", path));
+}
+
+/// 删除扫码登录保存的 web_login.json，用于导入 cookie 登录（其中的 refresh_token 属于之前的会话）和退出登录
+pub(crate) fn clear_web_login() {
+    clear_login_file("web_login.json");
+}
+
+/// 删除 TV 扫码登录保存的 tv_login.json
+pub(crate) fn clear_tv_login() {
+    clear_login_file("tv_login.json");
+}
+
+fn clear_login_file(name: &str) {
+    let login_path = format!("{}/{}", PROFILE_DIR.get().unwrap(), name);
+    if Path::new(login_path.as_str()).exists()
+        && let Err(e) = std::fs::remove_file(login_path.as_str())
+    {
+        super::out::warn(format!("无法删除配置文件 {} : {}", login_path, e).as_str());
     }
 }
 
-pub(crate) fn store_login(data: &bbdd::auth::web::WebLoginQRVerifyData)  {
-    let cookie_path = format!("{}/{}", CONFIG_DIR.get().unwrap(), "web_login.json");
-    std::fs::write(cookie_path.as_str(), serde_json::to_string_pretty(data).unwrap()).expect(
-        format!("无法写入配置文件 : {}", cookie_path).as_str(),
-    );
+/// 下载前根据 cookie/info 的 refresh 标记，使用 web_login.json 中的 refresh_token 刷新 cookie，
+/// 在 cookie 过期之前完成刷新
+pub(crate) async fn refresh_login_if_needed() {
+    let client = CLIENT_CELL.get().unwrap();
+    let login_path = format!("{}/{}", PROFILE_DIR.get().unwrap(), "web_login.json");
+    let Ok(text) = std::fs::read_to_string(login_path.as_str()) else {
+        return;
+    };
+    let Ok(mut login) = serde_json::from_str::<bbdd::auth::web::WebLoginQRVerifyData>(&text) else {
+        return;
+    };
+    match client
+        .web_refresh_cookie_if_needed(&login.refresh_token)
+        .await
+    {
+        Ok(None) => {}
+        Ok(Some(refresh_token)) => {
+            login.refresh_token = refresh_token;
+            login.timestamp = chrono::Utc::now().timestamp_millis();
+            if let Err(e) = std::fs::write(
+                login_path.as_str(),
+                serde_json::to_string_pretty(&login).unwrap(),
+            ) {
+                super::out::warn(format!("无法写入配置文件 {} : {}", login_path, e).as_str());
+            }
+            super::out::success("cookie 刷新成功");
+        }
+        Err(e) => super::out::warn(
+            format!(
+                "刷新 cookie 失败，将以当前状态继续下载，可运行 `bbdd login` 重新登录: {}",
+                super::format_bbdd_error(&e)
+            )
+            .as_str(),
+        ),
+    }
 }
@@ -1,7 +1,7 @@
 use crate::cmd::out::{error, info, success, warn};
 use bbdd::{BBDDError, BBDDResult};
-use dialoguer::Confirm;
-use futures::future;
+use dialoguer::{Confirm, MultiSelect, Select};
+use futures::{StreamExt, TryStreamExt, stream};
 use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
 use std::cmp::Reverse;
 use std::path::Path;
@@ -18,37 +18,189 @@ pub(crate) async fn download_avid(avid: i64) -> i32 {
             return 1;
         }
     };
-    let page = video_info.pages.first().expect("视频分P信息为空");
     info(format!("匹配到视频 : {}", video_info.title,).as_str());
-    let file_title = file_title(&video_info.title);
-    let merge_file = format!("{}.mp4", file_title);
-    if !continue_download(merge_file.as_str()) {
+    if video_info.is_stein_gate == 1 {
+        return download_stein(client, avid, &video_info, quality).await;
+    }
+    let pages = if *super::INTERACTIVE_SELECT.get().unwrap() {
+        select_pages(&video_info.pages)
+    } else {
+        vec![video_info.pages.first().expect("视频分P信息为空")]
+    };
+    if pages.is_empty() {
+        warn("未选择任何分P");
         return 0;
     }
+    let multi_pages = pages.len() > 1;
+    let mut failed_pages = Vec::new();
+    let mut success_pages = Vec::new();
+    for page in pages {
+        let default_title = if multi_pages || page.page != 1 {
+            format!("{}_P{}_{}", video_info.title, page.page, page.part)
+        } else {
+            video_info.title.clone()
+        };
+        let file_title = output_title(
+            default_title.as_str(),
+            &[
+                ("title", video_info.title.clone()),
+                ("page", page.page.to_string()),
+                ("part", page.part.clone()),
+                ("bvid", video_info.bvid.clone()),
+                ("aid", avid.to_string()),
+                ("cid", page.cid.to_string()),
+                ("owner", video_info.owner.name.clone()),
+            ],
+        );
+        let duration_ms = Some(page.duration * 1000);
+        if download_avid_page(
+            client,
+            avid,
+            page.cid,
+            duration_ms,
+            file_title.as_str(),
+            quality,
+        )
+        .await
+        {
+            success_pages.push(page);
+        } else {
+            failed_pages.push(page);
+        }
+    }
+    if failed_pages.is_empty() {
+        0
+    } else if success_pages.is_empty() {
+        1
+    } else {
+        2
+    }
+}
+
+/// 互动视频：遍历剧情图下载所有节点，并在同一目录中保存剧情图的 json 和 Graphviz 描述
+async fn download_stein(
+    client: &bbdd::BBDD,
+    avid: i64,
+    video_info: &bbdd::fetcher::VideoInfo,
+    quality: Option<i64>,
+) -> i32 {
+    info("该视频为互动视频，正在获取剧情图");
+    let graph = match client.fetch_stein_graph(avid, video_info.cid).await {
+        Ok(graph) => graph,
+        Err(err) => {
+            error(format!("无法获取互动视频剧情图: {}", super::format_bbdd_error(&err)).as_str());
+            return 1;
+        }
+    };
+    // 不同的选项可能跳转到同一段视频，按 cid 去重
+    let mut cids = std::collections::HashSet::new();
+    let nodes: Vec<&bbdd::stein::SteinNode> = graph
+        .ordered_nodes()
+        .into_iter()
+        .filter(|n| cids.insert(n.cid))
+        .collect();
+    info(format!("剧情图共 {} 个节点，{} 段视频", graph.nodes.len(), nodes.len()).as_str());
+    if graph.truncated {
+        warn("剧情图节点过多，只获取了部分节点，剧情图和下载的视频不完整");
+    }
+    if !enter_folder(&file_title(&video_info.title)).await {
+        return 1;
+    }
+    let json = serde_json::to_string_pretty(&graph).expect("序列化剧情图失败");
+    for (file, content) in [("stein_graph.json", json), ("stein_graph.dot", graph.to_dot())] {
+        match fs::write(file, content).await {
+            Ok(_) => success(format!("剧情图已保存: {}", file).as_str()),
+            Err(e) => warn(format!("无法保存剧情图 {}: {}", file, e).as_str()),
+        }
+    }
+    let nodes = if *super::INTERACTIVE_SELECT.get().unwrap() {
+        select_stein_nodes(&nodes)
+    } else {
+        nodes
+    };
+    let mut failed = 0;
+    for (index, node) in nodes.iter().enumerate() {
+        let file_title = output_title(
+            format!("{:02}_{}", index + 1, node.title).as_str(),
+            &[
+                ("title", video_info.title.clone()),
+                ("page", (index + 1).to_string()),
+                ("part", node.title.clone()),
+                ("bvid", video_info.bvid.clone()),
+                ("aid", avid.to_string()),
+                ("cid", node.cid.to_string()),
+                ("edge_id", node.edge_id.to_string()),
+                ("owner", video_info.owner.name.clone()),
+            ],
+        );
+        // 剧情图中没有各节点的时长，无法据此判断试看
+        if !download_avid_page(client, avid, node.cid, None, file_title.as_str(), quality).await {
+            failed += 1;
+        }
+    }
+    if failed == 0 {
+        0
+    } else if failed == nodes.len() {
+        1
+    } else {
+        2
+    }
+}
+
+/// duration_ms 为该分P的完整时长，未知时为 None
+async fn download_avid_page(
+    client: &bbdd::BBDD,
+    avid: i64,
+    cid: i64,
+    duration_ms: Option<i64>,
+    file_title: &str,
+    quality: Option<i64>,
+) -> bool {
+    let merge_file = merged_file_name(file_title);
+    if !continue_download(merge_file.as_str()) {
+        return true;
+    }
     let play_url = match client
-        .play_url_with_qn(avid, page.cid, quality.unwrap_or(127))
+        .play_url_with_qn(avid, cid, quality.unwrap_or(127))
         .await
     {
         Ok(play_url) => play_url,
         Err(err) => {
             error(format!("无法获取视频播放地址: {:?}", err).as_str());
-            return 1;
+            return false;
         }
     };
-    let video = match select_video(&play_url, quality) {
+    if !check_preview(&play_url, duration_ms, file_title) {
+        return false;
+    }
+    download_play_url(&play_url, file_title, merge_file.as_str(), quality).await
+}
+
+/// 按清晰度偏好选择视频流和音频流，下载后合并为 merge_file
+async fn download_play_url(
+    play_url: &bbdd::fetcher::VideoPlayUrl,
+    file_title: &str,
+    merge_file: &str,
+    quality: Option<i64>,
+) -> bool {
+    if play_url.is_durl() {
+        return download_durl(play_url, file_title, merge_file).await;
+    }
+    let video = match select_video(play_url, quality) {
         Ok(v) => v,
         Err(err) => {
             error(format!("无法获取视频下载地址: {:?}", err).as_str());
-            return 1;
+            return false;
         }
     };
-    let audio = match select_audio(&play_url) {
+    let audio = match select_audio(play_url) {
         Ok(a) => a,
         Err(err) => {
             error(format!("无法获取音频下载地址: {:?}", err).as_str());
-            return 1;
+            return false;
         }
     };
+    let (video, audio) = pick_streams(play_url, video, audio);
     info(
         format!(
             "选择清晰度: {} ({}x{}, video_bandwidth={}, audio_bandwidth={})",
@@ -67,7 +219,7 @@ pub(crate) async fn download_avid(avid: i64) -> i32 {
     let video_file = format!("{}.video.{}", file_title, video_id);
     let audio_file = format!("{}.audio.{}", file_title, audio_id);
 
-    info(format!("开始下载: “{}”", video_info.title).as_str());
+    info(format!("开始下载: “{}”", file_title).as_str());
 
     let result = download_and_cache_files(vec![
         (audio_file.as_str(), audio_urls, "音频"),
@@ -76,17 +228,64 @@ pub(crate) async fn download_avid(avid: i64) -> i32 {
     .await;
 
     if let Err(_e) = result {
-        return 1;
+        return false;
     }
-    match merge_files(
+    merge_files(
         vec![video_file.as_str(), audio_file.as_str()],
-        merge_file.as_str(),
+        merge_file,
+        &video,
     )
     .await
-    {
-        Ok(_) => 0,
-        Err(_) => 1,
+    .is_ok()
+}
+
+/// 接口只返回 FLV / MP4 分段时，下载全部分段后拼接为 merge_file
+async fn download_durl(
+    play_url: &bbdd::fetcher::VideoPlayUrl,
+    file_title: &str,
+    merge_file: &str,
+) -> bool {
+    let mut segments = play_url.durl.clone();
+    segments.sort_by_key(|s| s.order);
+    info(
+        format!(
+            "没有 DASH 视频流，使用 {} 分段下载: {} ({} 段)",
+            play_url.durl_extension().to_ascii_uppercase(),
+            bbdd::util::video_quality_to_string(play_url.quality as i32),
+            segments.len()
+        )
+        .as_str(),
+    );
+    let extension = play_url.durl_extension();
+    let segment_files: Vec<String> = segments
+        .iter()
+        .map(|s| format!("{}.part{}.{}", file_title, s.order, extension))
+        .collect();
+    let labels: Vec<String> = (1..=segments.len())
+        .map(|i| format!("分段 {}/{}", i, segments.len()))
+        .collect();
+
+    info(format!("开始下载: “{}”", file_title).as_str());
+
+    let result = download_and_cache_files(
+        segments
+            .iter()
+            .zip(&segment_files)
+            .zip(&labels)
+            .map(|((segment, file), label)| (file.as_str(), segment.urls(), label.as_str()))
+            .collect(),
+    )
+    .await;
+
+    if let Err(_e) = result {
+        return false;
     }
+    concat_files(
+        segment_files.iter().map(String::as_str).collect(),
+        merge_file,
+    )
+    .await
+    .is_ok()
 }
 
 pub(crate) async fn download_ep(ep_id: i64) -> i32 {
@@ -106,26 +305,30 @@ pub(crate) async fn download_ep(ep_id: i64) -> i32 {
         )
         .as_str(),
     );
-    let folder_name = file_title(&ep_info.season_title);
-    let folder_path = Path::new(folder_name.as_str());
-    if !folder_path.exists() {
-        if let Err(e) = fs::create_dir(folder_path).await {
-            error(format!("无法创建目录 {}: {}", folder_name, e).as_str());
-            return 1;
-        }
-    }
-    if let Err(e) = std::env::set_current_dir(folder_path) {
-        error(format!("无法切换工作目录: {}", e).as_str());
+    if !enter_folder(&file_title(&ep_info.season_title)).await {
         return 1;
-    } else {
-        success(format!("工作目录切换到: {}", folder_name).as_str());
     }
     let quality = *super::QUALITY_PREFERENCE.get().unwrap();
+    let episodes = if *super::INTERACTIVE_SELECT.get().unwrap() {
+        select_episodes(&ep_info.episodes)
+    } else {
+        ep_info.episodes.iter().collect()
+    };
     let mut failed_episodes = Vec::new();
     let mut success_episodes = Vec::new();
-    for x in &ep_info.episodes {
-        let file_title = file_title(&x.show_title);
-        let merge_file_name = format!("{}.mp4", file_title);
+    for x in episodes {
+        let file_title = output_title(
+            x.show_title.as_str(),
+            &[
+                ("title", x.show_title.clone()),
+                ("season", ep_info.season_title.clone()),
+                ("ep_id", x.ep_id.to_string()),
+                ("bvid", x.bvid.clone()),
+                ("aid", x.aid.to_string()),
+                ("cid", x.cid.to_string()),
+            ],
+        );
+        let merge_file_name = merged_file_name(file_title.as_str());
         if !continue_download(merge_file_name.as_str()) {
             continue;
         }
@@ -149,6 +352,18 @@ pub(crate) async fn download_ep(ep_id: i64) -> i32 {
                 continue;
             }
         };
+        if !check_preview(&play_url, Some(x.duration), x.show_title.as_str()) {
+            failed_episodes.push(x);
+            continue;
+        }
+        if play_url.is_durl() {
+            if download_durl(&play_url, file_title.as_str(), merge_file_name.as_str()).await {
+                success_episodes.push(x);
+            } else {
+                failed_episodes.push(x);
+            }
+            continue;
+        }
         let video = match select_video(&play_url, quality) {
             Ok(v) => v,
             Err(err) => {
@@ -165,6 +380,7 @@ pub(crate) async fn download_ep(ep_id: i64) -> i32 {
                 continue;
             }
         };
+        let (video, audio) = pick_streams(&play_url, video, audio);
         info(
             format!(
                 "选择清晰度: {} ({}x{}, video_bandwidth={}, audio_bandwidth={})",
@@ -195,6 +411,7 @@ pub(crate) async fn download_ep(ep_id: i64) -> i32 {
         if let Err(_) = merge_files(
             vec![video_file.as_str(), audio_file.as_str()],
             merge_file_name.as_str(),
+            &video,
         )
         .await
         {
@@ -212,6 +429,307 @@ pub(crate) async fn download_ep(ep_id: i64) -> i32 {
     }
 }
 
+/// bilibili.tv 番剧，链接中有 ep_id 时只下载该集，同时下载全部外挂字幕
+pub(crate) async fn download_intl(season_id: i64, ep_id: Option<i64>) -> i32 {
+    let client = super::client::CLIENT_CELL.get().unwrap();
+    let locale = super::CONFIG
+        .get()
+        .and_then(|c| c.locale.clone())
+        .unwrap_or_else(|| bbdd::intl::INTL_DEFAULT_LOCALE.to_string());
+    let season = match client.fetch_intl_season(season_id, locale.as_str()).await {
+        Ok(season) => season,
+        Err(err) => {
+            error(format!("无法获取番剧信息: {:?}", err).as_str());
+            return 1;
+        }
+    };
+    let all_episodes = match client.fetch_intl_episodes(season_id, locale.as_str()).await {
+        Ok(episodes) => episodes,
+        Err(err) => {
+            error(format!("无法获取剧集列表: {:?}", err).as_str());
+            return 1;
+        }
+    };
+    info(format!("匹配到番剧: {} (共{}个视频)", season.title, all_episodes.len()).as_str());
+    let episodes = if let Some(ep_id) = ep_id {
+        let episodes: Vec<_> = all_episodes
+            .iter()
+            .filter(|e| e.episode_id == ep_id)
+            .collect();
+        if episodes.is_empty() {
+            error(format!("番剧中没有找到剧集: {}", ep_id).as_str());
+            return 1;
+        }
+        episodes
+    } else if *super::INTERACTIVE_SELECT.get().unwrap() {
+        select_intl_episodes(&all_episodes)
+    } else {
+        all_episodes.iter().collect()
+    };
+    if !enter_folder(&file_title(&season.title)).await {
+        return 1;
+    }
+    let quality = *super::QUALITY_PREFERENCE.get().unwrap();
+    let mut failed_episodes = Vec::new();
+    let mut success_episodes = Vec::new();
+    for episode in episodes {
+        let show_title = episode.show_title();
+        let file_title = output_title(
+            show_title.as_str(),
+            &[
+                ("title", show_title.clone()),
+                ("season", season.title.clone()),
+                ("ep_id", episode.episode_id.to_string()),
+            ],
+        );
+        let merge_file = merged_file_name(file_title.as_str());
+        if continue_download(merge_file.as_str()) {
+            let play_url = match client
+                .intl_play_url(episode.episode_id, quality.unwrap_or(127), locale.as_str())
+                .await
+            {
+                Ok(play_url) => play_url,
+                Err(err) => {
+                    error(format!("无法获取视频 {} 的播放地址: {:?}", show_title, err).as_str());
+                    failed_episodes.push(episode);
+                    continue;
+                }
+            };
+            if !check_preview(&play_url, None, show_title.as_str())
+                || !download_play_url(&play_url, file_title.as_str(), merge_file.as_str(), quality)
+                    .await
+            {
+                failed_episodes.push(episode);
+                continue;
+            }
+        }
+        download_intl_subtitles(client, episode.episode_id, file_title.as_str(), &locale).await;
+        success_episodes.push(episode);
+    }
+    if failed_episodes.is_empty() {
+        0
+    } else if success_episodes.is_empty() {
+        1
+    } else {
+        2
+    }
+}
+
+/// 字幕保存为 {file_title}.{lang_key}.srt / .ass，失败时只提示
+async fn download_intl_subtitles(client: &bbdd::BBDD, ep_id: i64, file_title: &str, locale: &str) {
+    let subtitles = match client.fetch_intl_subtitles(ep_id, locale).await {
+        Ok(subtitles) => subtitles,
+        Err(err) => {
+            warn(format!("无法获取字幕列表: {:?}", err).as_str());
+            return;
+        }
+    };
+    for subtitle in subtitles {
+        let file_name = format!(
+            "{}.{}.{}",
+            file_title,
+            self::file_title(&subtitle.lang_key),
+            subtitle.extension()
+        );
+        if !continue_download(file_name.as_str()) {
+            continue;
+        }
+        let result = match client.download_intl_subtitle(&subtitle).await {
+            Ok(text) => fs::write(file_name.as_str(), text)
+                .await
+                .map_err(|e| BBDDError::StateError(e.to_string())),
+            Err(err) => Err(err),
+        };
+        match result {
+            Ok(_) => success(format!("字幕已保存: {} ({})", file_name, subtitle.lang).as_str()),
+            Err(err) => warn(format!("无法下载字幕 {}: {:?}", subtitle.lang, err).as_str()),
+        }
+    }
+}
+
+/// 下载单曲，写入封面、歌词等标签
+pub(crate) async fn download_audio(sid: i64) -> i32 {
+    let client = super::client::CLIENT_CELL.get().unwrap();
+    let song = match client.fetch_audio_info(sid).await {
+        Ok(song) => song,
+        Err(err) => {
+            error(format!("无法获取音频信息: {:?}", err).as_str());
+            return 1;
+        }
+    };
+    info(format!("匹配到音频 : {}", song.title).as_str());
+    if download_audio_song(client, &song, None).await {
+        0
+    } else {
+        1
+    }
+}
+
+/// 下载歌单，歌单名称作为目录名和专辑名
+pub(crate) async fn download_audio_menu(sid: i64) -> i32 {
+    let client = super::client::CLIENT_CELL.get().unwrap();
+    let menu = match client.fetch_audio_menu_info(sid).await {
+        Ok(menu) => menu,
+        Err(err) => {
+            error(format!("无法获取歌单信息: {:?}", err).as_str());
+            return 1;
+        }
+    };
+    let songs = match client.fetch_audio_menu_songs(sid).await {
+        Ok(songs) => songs,
+        Err(err) => {
+            error(format!("无法获取歌单歌曲: {:?}", err).as_str());
+            return 1;
+        }
+    };
+    info(format!("匹配到歌单: {} (共{}首)", menu.title, songs.len()).as_str());
+    if !enter_folder(&file_title(&menu.title)).await {
+        return 1;
+    }
+    let songs = if *super::INTERACTIVE_SELECT.get().unwrap() {
+        select_audio_songs(&songs)
+    } else {
+        songs.iter().collect()
+    };
+    let mut failed_songs = Vec::new();
+    let mut success_songs = Vec::new();
+    for song in songs {
+        if download_audio_song(client, song, Some(menu.title.as_str())).await {
+            success_songs.push(song);
+        } else {
+            failed_songs.push(song);
+        }
+    }
+    if failed_songs.is_empty() {
+        0
+    } else if success_songs.is_empty() {
+        1
+    } else {
+        2
+    }
+}
+
+async fn download_audio_song(
+    client: &bbdd::BBDD,
+    song: &bbdd::audio::AudioSongInfo,
+    album: Option<&str>,
+) -> bool {
+    let file_title = output_title(
+        song.title.as_str(),
+        &[
+            ("title", song.title.clone()),
+            ("author", song.author.clone()),
+            ("owner", song.uname.clone()),
+            ("sid", song.id.to_string()),
+        ],
+    );
+    let stream = match client
+        .audio_stream_url(song.id, bbdd::audio::AUDIO_QUALITY_FLAC)
+        .await
+    {
+        Ok(stream) => stream,
+        Err(err) => {
+            error(format!("无法获取音频下载地址: {:?}", err).as_str());
+            return false;
+        }
+    };
+    let output_file = format!("{}.{}", file_title, stream.extension());
+    if !continue_download(output_file.as_str()) {
+        return true;
+    }
+    let quality_desc = stream
+        .qualities
+        .iter()
+        .find(|q| q.quality == stream.quality)
+        .map(|q| q.desc.clone())
+        .unwrap_or_else(|| stream.quality.to_string());
+    info(format!("选择音质: {}", quality_desc).as_str());
+    let audio_file = format!("{}.audio.{}", file_title, stream.quality);
+    // 封面的格式由 ffmpeg 按扩展名判断，沿用地址中的扩展名
+    let cover_ext = Path::new(song.cover.split('?').next().unwrap_or_default())
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or("jpg");
+    let cover_file = format!("{}.cover.{}", file_title, cover_ext);
+    let mut files = vec![(audio_file.as_str(), stream.cdns.clone(), "音频")];
+    if !song.cover.is_empty() {
+        files.push((cover_file.as_str(), vec![song.cover.clone()], "封面"));
+    }
+    info(format!("开始下载: “{}”", file_title).as_str());
+    if download_and_cache_files(files).await.is_err() {
+        return false;
+    }
+    let lyric = match client.fetch_audio_lyric(song.id).await {
+        Ok(lyric) => lyric,
+        Err(err) => {
+            warn(format!("无法获取歌词: {:?}", err).as_str());
+            String::new()
+        }
+    };
+    let artist = if song.author.is_empty() {
+        song.uname.clone()
+    } else {
+        song.author.clone()
+    };
+    let mut metadata = vec![("title", song.title.clone()), ("artist", artist)];
+    if let Some(album) = album {
+        metadata.push(("album", album.to_string()));
+    }
+    if !song.intro.is_empty() {
+        metadata.push(("comment", song.intro.clone()));
+    }
+    if !lyric.is_empty() {
+        metadata.push(("lyrics", lyric));
+    }
+    let cover = (!song.cover.is_empty()).then_some(cover_file.as_str());
+    tag_audio_file(audio_file.as_str(), cover, &metadata, output_file.as_str())
+        .await
+        .is_ok()
+}
+
+/// 只能获取试看片段时默认跳过，使用 --allow-preview 时提示后继续下载
+fn check_preview(
+    play_url: &bbdd::fetcher::VideoPlayUrl,
+    duration_ms: Option<i64>,
+    title: &str,
+) -> bool {
+    let bbdd::fetcher::PreviewStatus::Preview {
+        preview_ms,
+        duration_ms,
+    } = play_url.preview_status(duration_ms)
+    else {
+        return true;
+    };
+    let length = if duration_ms > 0 {
+        format!(
+            "{}，完整时长 {}",
+            format_duration(preview_ms / 1000),
+            format_duration(duration_ms / 1000)
+        )
+    } else {
+        format_duration(preview_ms / 1000)
+    };
+    if *super::ALLOW_PREVIEW.get().unwrap() {
+        warn(
+            format!(
+                "“{}” 只能获取试看片段（{}），下载的视频不完整",
+                title, length
+            )
+            .as_str(),
+        );
+        true
+    } else {
+        error(
+            format!(
+                "“{}” 只能获取试看片段（{}），可能需要登录或大会员，已跳过。使用 --allow-preview 下载试看片段",
+                title, length
+            )
+            .as_str(),
+        );
+        false
+    }
+}
+
 fn select_video(play_url: &bbdd::fetcher::VideoPlayUrl, quality: Option<i64>) -> BBDDResult<bbdd::fetcher::VideoMedia> {
     let selected = if let Some(q) = quality {
         play_url
@@ -251,6 +769,197 @@ fn select_audio(play_url: &bbdd::fetcher::VideoPlayUrl) -> BBDDResult<bbdd::fetc
         .ok_or(BBDDError::StateError("音频下载地址列表为空".to_string()))
 }
 
+fn select_pages(pages: &[bbdd::fetcher::VideoPage]) -> Vec<&bbdd::fetcher::VideoPage> {
+    if pages.len() <= 1 {
+        return pages.iter().collect();
+    }
+    let items: Vec<String> = pages
+        .iter()
+        .map(|p| format!("P{} {} ({})", p.page, p.part, format_duration(p.duration)))
+        .collect();
+    let selected = multi_select("选择要下载的分P（空格切换，回车确认）", &items);
+    selected.into_iter().map(|i| &pages[i]).collect()
+}
+
+fn select_stein_nodes<'a>(
+    nodes: &[&'a bbdd::stein::SteinNode],
+) -> Vec<&'a bbdd::stein::SteinNode> {
+    if nodes.len() <= 1 {
+        return nodes.to_vec();
+    }
+    let items: Vec<String> = nodes
+        .iter()
+        .map(|n| if n.is_leaf { format!("{} [结局]", n.title) } else { n.title.clone() })
+        .collect();
+    let selected = multi_select("选择要下载的剧情节点（空格切换，回车确认）", &items);
+    selected.into_iter().map(|i| nodes[i]).collect()
+}
+
+fn select_episodes(episodes: &[bbdd::fetcher::EpisodeInfo]) -> Vec<&bbdd::fetcher::EpisodeInfo> {
+    if episodes.len() <= 1 {
+        return episodes.iter().collect();
+    }
+    let items: Vec<String> = episodes
+        .iter()
+        .map(|e| {
+            if e.badge.is_empty() {
+                e.show_title.clone()
+            } else {
+                format!("{} [{}]", e.show_title, e.badge)
+            }
+        })
+        .collect();
+    let selected = multi_select("选择要下载的剧集（空格切换，回车确认）", &items);
+    selected.into_iter().map(|i| &episodes[i]).collect()
+}
+
+fn select_intl_episodes(episodes: &[bbdd::intl::IntlEpisode]) -> Vec<&bbdd::intl::IntlEpisode> {
+    if episodes.len() <= 1 {
+        return episodes.iter().collect();
+    }
+    let items: Vec<String> = episodes.iter().map(|e| e.show_title()).collect();
+    let selected = multi_select("选择要下载的剧集（空格切换，回车确认）", &items);
+    selected.into_iter().map(|i| &episodes[i]).collect()
+}
+
+fn select_audio_songs(songs: &[bbdd::audio::AudioSongInfo]) -> Vec<&bbdd::audio::AudioSongInfo> {
+    if songs.len() <= 1 {
+        return songs.iter().collect();
+    }
+    let items: Vec<String> = songs
+        .iter()
+        .map(|s| {
+            format!(
+                "{} - {} ({})",
+                s.title,
+                s.author,
+                format_duration(s.duration)
+            )
+        })
+        .collect();
+    let selected = multi_select("选择要下载的歌曲（空格切换，回车确认）", &items);
+    selected.into_iter().map(|i| &songs[i]).collect()
+}
+
+/// 交互式多选，默认全选；无法显示选择框（例如没有终端）时报错退出，避免当作未选择任何项
+fn multi_select(prompt: &str, items: &[String]) -> Vec<usize> {
+    let defaults = vec![true; items.len()];
+    super::error_exit(
+        MultiSelect::new()
+            .with_prompt(prompt)
+            .items(items)
+            .defaults(&defaults)
+            .interact()
+            .map_err(|e| BBDDError::StateError(format!("无法显示选择框: {}", e))),
+    )
+}
+
+/// 交互模式下，列出接口实际返回的视频流和音频流供用户选择，默认项为自动选择的结果
+fn pick_streams(
+    play_url: &bbdd::fetcher::VideoPlayUrl,
+    video: bbdd::fetcher::VideoMedia,
+    audio: bbdd::fetcher::VideoMedia,
+) -> (bbdd::fetcher::VideoMedia, bbdd::fetcher::VideoMedia) {
+    if !*super::INTERACTIVE_SELECT.get().unwrap() {
+        return (video, audio);
+    }
+    let mut videos = play_url.dash.video.clone();
+    videos.sort_by_key(|v| Reverse((v.id, codec_rank(&v.codecs), v.bandwidth)));
+    let mut audios = play_url.dash.audio.clone();
+    audios.sort_by_key(|a| Reverse(a.bandwidth));
+    let video = pick_stream("选择视频流", videos, video, |v| {
+        format!(
+            "{} {}x{} {} {}fps {}kbps",
+            bbdd::util::video_quality_to_string(v.id as i32),
+            v.width,
+            v.height,
+            v.codecs,
+            v.frame_rate,
+            v.bandwidth / 1000
+        )
+    });
+    let audio = pick_stream("选择音频流", audios, audio, |a| {
+        format!(
+            "{} {} {}kbps",
+            bbdd::util::audio_quality_to_string(a.id as i32),
+            a.codecs,
+            a.bandwidth / 1000
+        )
+    });
+    (video, audio)
+}
+
+fn pick_stream(
+    prompt: &str,
+    list: Vec<bbdd::fetcher::VideoMedia>,
+    default: bbdd::fetcher::VideoMedia,
+    label: impl Fn(&bbdd::fetcher::VideoMedia) -> String,
+) -> bbdd::fetcher::VideoMedia {
+    if list.len() <= 1 {
+        return default;
+    }
+    let default_index = list.iter().position(|m| m == &default).unwrap_or(0);
+    let items: Vec<String> = list.iter().map(label).collect();
+    // 与 multi_select 相同，无法显示选择框时报错退出，而不是下载默认的流
+    let index = super::error_exit(
+        Select::new()
+            .with_prompt(prompt)
+            .items(&items)
+            .default(default_index)
+            .interact()
+            .map_err(|e| BBDDError::StateError(format!("无法显示选择框: {}", e))),
+    );
+    list.into_iter().nth(index).unwrap_or(default)
+}
+
+/// 合并后的视频文件名，扩展名为 --container 指定的容器
+fn merged_file_name(file_title: &str) -> String {
+    let container = super::CONFIG
+        .get()
+        .and_then(|c| c.container.as_deref())
+        .unwrap_or("mp4");
+    format!("{}.{}", file_title, container)
+}
+
+/// HDR 和杜比视界的配置需要容器支持，否则播放器会按 SDR 播放
+fn check_container(video: &bbdd::fetcher::VideoMedia, output_file: &str) {
+    let label = match video.dynamic_range() {
+        bbdd::fetcher::DynamicRange::Sdr => return,
+        bbdd::fetcher::DynamicRange::Hdr => "HDR",
+        bbdd::fetcher::DynamicRange::DolbyVision => "杜比视界",
+    };
+    info(
+        format!(
+            "检测到{}视频流 ({})，合并时保留色彩和{}配置",
+            label, video.codecs, label
+        )
+        .as_str(),
+    );
+    let extension = Path::new(output_file)
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    match extension.as_str() {
+        "mp4" => {}
+        "mkv" if video.dynamic_range() == bbdd::fetcher::DynamicRange::Hdr => {}
+        "mkv" => warn(
+            "杜比视界写入 MKV 需要 FFmpeg 6.0 及以上，且多数设备只识别 MP4 中的杜比视界配置，建议使用 --container mp4",
+        ),
+        _ => warn(
+            format!(
+                "{} 容器无法保存{}配置，播放时可能按 SDR 显示，建议使用 --container mp4",
+                extension, label
+            )
+            .as_str(),
+        ),
+    }
+}
+
+fn format_duration(seconds: i64) -> String {
+    format!("{:02}:{:02}", seconds / 60, seconds % 60)
+}
+
 fn media_urls(media: &bbdd::fetcher::VideoMedia) -> Vec<String> {
     let mut urls = vec![media.base_url.clone()];
     urls.extend(media.backup_url.clone());
@@ -259,20 +968,31 @@ fn media_urls(media: &bbdd::fetcher::VideoMedia) -> Vec<String> {
 
 fn codec_rank(codecs: &str) -> i32 {
     let codecs_lower = codecs.to_ascii_lowercase();
-    if codecs_lower.contains("av01") {
-        3
+    let (codec, rank) = if codecs_lower.contains("av01") {
+        ("av1", 3)
     } else if codecs_lower.contains("hev1") || codecs_lower.contains("hvc1") || codecs_lower.contains("hevc") {
-        2
+        ("hevc", 2)
     } else if codecs_lower.contains("avc") || codecs_lower.contains("h264") {
-        1
+        ("avc", 1)
     } else {
-        0
+        return 0;
+    };
+    // 配置了编码偏好时，偏好的编码排在最前
+    match super::CONFIG.get().and_then(|c| c.codec.as_deref()) {
+        Some(preference) if preference == codec => 4,
+        _ => rank,
     }
 }
 
-async fn merge_files(input_files: Vec<&str>, output_file: &str) -> BBDDResult<()> {
+async fn merge_files(
+    input_files: Vec<&str>,
+    output_file: &str,
+    video: &bbdd::fetcher::VideoMedia,
+) -> BBDDResult<()> {
     info(format!("开始合并文件到: {}", output_file).as_str());
-    let result = super::ffmpeg::ffmpeg_api::ffmpeg_merge_files(input_files.clone(), output_file);
+    check_container(video, output_file);
+    let result =
+        super::ffmpeg::ffmpeg_api::ffmpeg_merge_files(input_files.clone(), output_file, video);
     match result {
         Ok(_) => {
             for file in &input_files {
@@ -290,6 +1010,52 @@ async fn merge_files(input_files: Vec<&str>, output_file: &str) -> BBDDResult<()
     }
 }
 
+async fn concat_files(input_files: Vec<&str>, output_file: &str) -> BBDDResult<()> {
+    info(format!("开始拼接分段到: {}", output_file).as_str());
+    let result = super::ffmpeg::ffmpeg_api::ffmpeg_concat_files(input_files.clone(), output_file);
+    match result {
+        Ok(_) => {
+            for file in &input_files {
+                let _ = fs::remove_file(file).await;
+            }
+            success(format!("拼接完成: {}", output_file).as_str());
+            Ok(())
+        }
+        Err(err) => {
+            let _ = fs::remove_file(output_file).await;
+            cleanup_temp_files_on_fail(input_files).await;
+            error(format!("拼接失败: {:?}", err).as_str());
+            Err(BBDDError::StateError(format!("拼接失败: {:?}", err)))
+        }
+    }
+}
+
+async fn tag_audio_file(
+    audio_file: &str,
+    cover_file: Option<&str>,
+    metadata: &[(&str, String)],
+    output_file: &str,
+) -> BBDDResult<()> {
+    let input_files: Vec<&str> = std::iter::once(audio_file).chain(cover_file).collect();
+    let result =
+        super::ffmpeg::ffmpeg_api::ffmpeg_tag_audio(audio_file, cover_file, metadata, output_file);
+    match result {
+        Ok(_) => {
+            for file in &input_files {
+                let _ = fs::remove_file(file).await;
+            }
+            success(format!("下载完成: {}", output_file).as_str());
+            Ok(())
+        }
+        Err(err) => {
+            let _ = fs::remove_file(output_file).await;
+            cleanup_temp_files_on_fail(input_files).await;
+            error(format!("写入音频标签失败: {:?}", err).as_str());
+            Err(BBDDError::StateError(format!("写入音频标签失败: {:?}", err)))
+        }
+    }
+}
+
 async fn cleanup_temp_files_on_fail(files: Vec<&str>) {
     let continue_cache = *super::CONTINUE_CACHE.get().unwrap();
     if !continue_cache {
@@ -358,32 +1124,29 @@ async fn download_files(
     )>,
 ) -> Result<(), Box<dyn std::error::Error>> {
     let continue_cache = *super::CONTINUE_CACHE.get().unwrap();
+    let concurrency = super::CONFIG
+        .get()
+        .and_then(|c| c.concurrency)
+        .unwrap_or(2);
     let client = super::client::CLIENT_CELL.get().unwrap();
-    // 获取每个文件的长度并创建进度条
-    let mut bars = Vec::new();
     let m = MultiProgress::new();
-    for (file_name, urls, label) in files {
-        let path = Path::new(file_name);
-        let Some((file, resp, file_len, len)) =
-            open_with_backup(client, path, urls, continue_cache, file_name).await?
-        else {
-            continue;
-        };
-        let pb = m.add(ProgressBar::new(len));
-        pb.set_style(
-            ProgressStyle::default_bar()
-                .template(&format!(
-                    "{{msg}} [{{bar:40.cyan/blue}}] {{bytes}}/{{total_bytes}} ({{eta}})"
-                ))?
-                .progress_chars("##-"),
-        );
-        pb.set_message(label.to_string());
-        bars.push((file, pb, resp, len, file_len));
-    }
-    // 下载任务
-    let tasks: Vec<_> = bars
-        .into_iter()
-        .map(|(mut file, pb, mut resp, len, file_len)| async move {
+    // 下载任务，按配置的并发数同时进行，每个任务在开始时才建立连接并创建进度条
+    let tasks = files.into_iter().map(|(file_name, urls, label)| {
+        let m = &m;
+        async move {
+            let path = Path::new(file_name);
+            let Some((mut file, mut resp, file_len, len)) =
+                open_with_backup(client, path, urls, continue_cache, file_name).await?
+            else {
+                return Ok::<_, Box<dyn std::error::Error>>(());
+            };
+            let pb = m.add(ProgressBar::new(len));
+            pb.set_style(
+                ProgressStyle::default_bar()
+                    .template("{msg} [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({eta})")?
+                    .progress_chars("##-"),
+            );
+            pb.set_message(label.to_string());
             if file_len > 0 {
                 pb.set_position(file_len);
             }
@@ -403,9 +1166,12 @@ async fn download_files(
             pb.inc(cum as u64);
             pb.finish();
             Ok::<_, Box<dyn std::error::Error>>(())
-        })
-        .collect();
-    let _ = future::try_join_all(tasks).await?;
+        }
+    });
+    let _: Vec<()> = stream::iter(tasks)
+        .buffer_unordered(concurrency)
+        .try_collect()
+        .await?;
     Ok(())
 }
 
@@ -485,7 +1251,39 @@ async fn open_with_backup(
     Err(err_msg.into())
 }
 
-fn file_title(title: &str) -> String {
+/// 按配置的输出模板生成文件名，未配置模板时使用默认文件名
+fn output_title(default: &str, vars: &[(&str, String)]) -> String {
+    match super::CONFIG.get().and_then(|c| c.output.as_ref()) {
+        Some(template) => {
+            let mut title = template.clone();
+            for (key, value) in vars {
+                title = title.replace(format!("{{{}}}", key).as_str(), value);
+            }
+            file_title(&title)
+        }
+        None => file_title(default),
+    }
+}
+
+/// 创建目录（已存在时直接使用）并切换工作目录
+async fn enter_folder(folder_name: &str) -> bool {
+    let folder_path = Path::new(folder_name);
+    if !folder_path.exists()
+        && let Err(e) = fs::create_dir(folder_path).await
+    {
+        error(format!("无法创建目录 {}: {}", folder_name, e).as_str());
+        return false;
+    }
+    if let Err(e) = std::env::set_current_dir(folder_path) {
+        error(format!("无法切换工作目录: {}", e).as_str());
+        false
+    } else {
+        success(format!("工作目录切换到: {}", folder_name).as_str());
+        true
+    }
+}
+
+pub(crate) fn file_title(title: &str) -> String {
     let invalid_chars = ['<', '>', ':', '"', '/', '\\', '|', '?', '*'];
     let mut file_title = title.to_string();
     for ch in invalid_chars.iter() {
@@ -17,7 +17,11 @@ pub(crate) mod ffmpeg_api {
         }
     }
 
-    pub(crate) fn ffmpeg_merge_files(list: Vec<&str>, output: &str) -> Result<(), Box<dyn std::error::Error>> {
+    pub(crate) fn ffmpeg_merge_files(
+        list: Vec<&str>,
+        output: &str,
+        video: &bbdd::fetcher::VideoMedia,
+    ) -> Result<(), Box<dyn std::error::Error>> {
         info("正在合并文件");
         let mut cmd = std::process::Command::new("ffmpeg");
         cmd.stderr(Stdio::null());
@@ -30,6 +34,19 @@ pub(crate) mod ffmpeg_api {
         cmd.arg("copy");
         cmd.arg("-acodec");
         cmd.arg("copy");
+        if output.ends_with(".mp4") {
+            match video.dynamic_range() {
+                bbdd::fetcher::DynamicRange::Sdr => {}
+                bbdd::fetcher::DynamicRange::Hdr => {
+                    cmd.args(["-movflags", "+write_colr"]);
+                }
+                bbdd::fetcher::DynamicRange::DolbyVision => {
+                    // mp4 中的 dvcC/dvvC 是非官方扩展，需要 -strict unofficial 才会写入
+                    cmd.args(["-movflags", "+write_colr", "-strict", "unofficial"]);
+                    cmd.args(["-tag:v", video.codec_tag()]);
+                }
+            }
+        }
         cmd.arg("-y");
         cmd.arg(output);
         let status = cmd.status().unwrap();
@@ -42,15 +59,92 @@ pub(crate) mod ffmpeg_api {
             ))))
         }
     }
+
+    /// 使用 concat demuxer 按顺序拼接 durl 分段，不重新编码
+    pub(crate) fn ffmpeg_concat_files(
+        list: Vec<&str>,
+        output: &str,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        info("正在拼接分段");
+        let list_file = format!("{}.concat.txt", output);
+        let mut content = String::new();
+        for x in &list {
+            let path = std::path::absolute(x)?;
+            content.push_str(
+                format!("file '{}'\n", path.to_string_lossy().replace('\'', "'\\''")).as_str(),
+            );
+        }
+        std::fs::write(&list_file, content)?;
+        let mut cmd = std::process::Command::new("ffmpeg");
+        cmd.stderr(Stdio::null());
+        cmd.stdout(Stdio::null());
+        cmd.args(["-f", "concat", "-safe", "0", "-i"]);
+        cmd.arg(&list_file);
+        cmd.args(["-c", "copy"]);
+        cmd.arg("-y");
+        cmd.arg(output);
+        let status = cmd.status();
+        let _ = std::fs::remove_file(&list_file);
+        let status = status?;
+        if status.success() {
+            Ok(())
+        } else {
+            Err(Box::new(bbdd::BBDDError::StateError(format!(
+                "FFMPEG 未能成功运行 : EXIT CODE : {:?}",
+                status.code()
+            ))))
+        }
+    }
+
+    /// 不重新编码，将封面和元数据（标题、歌手、歌词等）写入音频文件
+    pub(crate) fn ffmpeg_tag_audio(
+        audio: &str,
+        cover: Option<&str>,
+        metadata: &[(&str, String)],
+        output: &str,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        info("正在写入音频标签");
+        let mut cmd = std::process::Command::new("ffmpeg");
+        cmd.stderr(Stdio::null());
+        cmd.stdout(Stdio::null());
+        cmd.arg("-i");
+        cmd.arg(audio);
+        if let Some(cover) = cover {
+            cmd.arg("-i");
+            cmd.arg(cover);
+        }
+        cmd.args(["-map", "0:a"]);
+        if cover.is_some() {
+            cmd.args(["-map", "1:v", "-disposition:v:0", "attached_pic"]);
+        }
+        cmd.args(["-c", "copy"]);
+        for (key, value) in metadata {
+            cmd.arg("-metadata");
+            cmd.arg(format!("{}={}", key, value));
+        }
+        cmd.arg("-y");
+        cmd.arg(output);
+        let status = cmd.status()?;
+        if status.success() {
+            Ok(())
+        } else {
+            Err(Box::new(bbdd::BBDDError::StateError(format!(
+                "FFMPEG 未能成功运行 : EXIT CODE : {:?}",
+                status.code()
+            ))))
+        }
+    }
 }
 
 #[cfg(feature = "rsmpeg")]
 pub(crate) mod ffmpeg_api {
+    use bbdd::fetcher::DynamicRange;
     use indicatif::ProgressBar;
     use rsmpeg::{
         self,
         avcodec::{AVCodec, AVCodecContext},
         avformat::{AVFormatContextInput, AVFormatContextOutput},
+        avutil::AVDictionary,
     };
     use std::collections::HashMap;
     use std::ffi::CString;
@@ -59,7 +153,10 @@ pub(crate) mod ffmpeg_api {
     pub fn ffmpeg_merge_files(
         list: Vec<&str>,
         output: &str,
+        video: &bbdd::fetcher::VideoMedia,
     ) -> Result<(), Box<dyn std::error::Error>> {
+        let dynamic_range = video.dynamic_range();
+        let mp4 = output.ends_with(".mp4");
         let mut total_packets = 0;
         for input in &list {
             let input = CString::new(*input)?;
@@ -84,12 +181,27 @@ pub(crate) mod ffmpeg_api {
         let output = CString::new(output)?;
         let mut output_format_context = AVFormatContextOutput::create(&output)?;
         let mut inputs = vec![];
-        for input in list {
+        for (index, input) in list.into_iter().enumerate() {
             let input = CString::new(input).unwrap();
             let input_format_context = AVFormatContextInput::open(&input)?;
             let mut stream_index_map = HashMap::new();
             for av_stream_ref in input_format_context.streams() {
                 let stream_codecpar = av_stream_ref.codecpar();
+                // 第一个输入为视频，HDR 和杜比视界直接复制 codecpar，
+                // 保留色彩信息和 coded_side_data 中的 DOVI 配置（FFmpeg 6.1 及以上）
+                if index == 0 && dynamic_range != DynamicRange::Sdr {
+                    let mut out_stream = output_format_context.new_stream();
+                    out_stream.set_codecpar(stream_codecpar.clone());
+                    out_stream.set_time_base(av_stream_ref.time_base);
+                    if !mp4 {
+                        // dvh1 等 sample entry 只适用于 mp4，由其他容器自行选择
+                        unsafe {
+                            (*(*out_stream.as_mut_ptr()).codecpar).codec_tag = 0;
+                        }
+                    }
+                    stream_index_map.insert(av_stream_ref.index as i32, out_stream.index as i32);
+                    continue;
+                }
                 let codec_id = stream_codecpar.codec_id;
                 let decoder = AVCodec::find_decoder(codec_id).ok_or(
                     bbdd::BBDDError::StateError(format!("Unsupported codec_id: {:?}", codec_id)),
@@ -107,7 +219,18 @@ pub(crate) mod ffmpeg_api {
             }
             inputs.push((input_format_context, stream_index_map));
         }
-        let mut dict = None;
+        if dynamic_range == DynamicRange::DolbyVision {
+            // mp4 中的 dvcC/dvvC 是非官方扩展，需要降低标准要求才会写入
+            unsafe {
+                (*output_format_context.as_mut_ptr()).strict_std_compliance =
+                    rsmpeg::ffi::FF_COMPLIANCE_UNOFFICIAL as c_int;
+            }
+        }
+        let mut dict = if mp4 && dynamic_range != DynamicRange::Sdr {
+            Some(AVDictionary::new(c"movflags", c"+write_colr", 0))
+        } else {
+            None
+        };
         output_format_context.write_header(&mut dict)?;
         for (mut input_format_context, stream_index_map) in inputs {
             loop {
@@ -131,4 +254,115 @@ pub(crate) mod ffmpeg_api {
         pb.finish_with_message("合并完成".to_owned());
         Ok(())
     }
+
+    /// 按顺序拼接 durl 分段，不重新编码，后一个分段的时间戳接在前一个分段之后
+    pub fn ffmpeg_concat_files(
+        list: Vec<&str>,
+        output: &str,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let output = CString::new(output)?;
+        let mut output_format_context = AVFormatContextOutput::create(&output)?;
+        let mut inputs = vec![];
+        for input in list {
+            let input = CString::new(input)?;
+            inputs.push(AVFormatContextInput::open(&input)?);
+        }
+        // 各分段的流结构相同，按第一个分段创建输出流
+        let first = inputs.first().ok_or(bbdd::BBDDError::StateError(
+            "没有需要拼接的分段".to_string(),
+        ))?;
+        for av_stream_ref in first.streams() {
+            let mut out_stream = output_format_context.new_stream();
+            out_stream.set_codecpar(av_stream_ref.codecpar().clone());
+            out_stream.set_time_base(av_stream_ref.time_base);
+        }
+        let mut dict = None;
+        output_format_context.write_header(&mut dict)?;
+        let mut output_time_bases = vec![];
+        for av_stream_ref in output_format_context.streams() {
+            output_time_bases.push(av_stream_ref.time_base);
+        }
+        let mut offsets = vec![0i64; output_time_bases.len()];
+        for mut input_format_context in inputs {
+            let mut input_time_bases = vec![];
+            for av_stream_ref in input_format_context.streams() {
+                input_time_bases.push(av_stream_ref.time_base);
+            }
+            let mut ends = offsets.clone();
+            while let Some(mut packet) = input_format_context.read_packet()? {
+                let index = packet.stream_index as usize;
+                if index >= offsets.len() || index >= input_time_bases.len() {
+                    continue;
+                }
+                packet.rescale_ts(input_time_bases[index], output_time_bases[index]);
+                if packet.pts != rsmpeg::ffi::AV_NOPTS_VALUE {
+                    packet.set_pts(packet.pts + offsets[index]);
+                }
+                if packet.dts != rsmpeg::ffi::AV_NOPTS_VALUE {
+                    packet.set_dts(packet.dts + offsets[index]);
+                    ends[index] = ends[index].max(packet.dts + packet.duration);
+                }
+                output_format_context.interleaved_write_frame(&mut packet)?;
+            }
+            offsets = ends;
+        }
+        output_format_context.write_trailer()?;
+        Ok(())
+    }
+
+    /// 不重新编码，将封面和元数据（标题、歌手、歌词等）写入音频文件
+    pub fn ffmpeg_tag_audio(
+        audio: &str,
+        cover: Option<&str>,
+        metadata: &[(&str, String)],
+        output: &str,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let output = CString::new(output)?;
+        let mut output_format_context = AVFormatContextOutput::create(&output)?;
+        let mut inputs = vec![];
+        for (index, input) in std::iter::once(audio).chain(cover).enumerate() {
+            let input = CString::new(input)?;
+            let input_format_context = AVFormatContextInput::open(&input)?;
+            let mut stream_index_map = HashMap::new();
+            for av_stream_ref in input_format_context.streams() {
+                let mut out_stream = output_format_context.new_stream();
+                out_stream.set_codecpar(av_stream_ref.codecpar().clone());
+                out_stream.set_time_base(av_stream_ref.time_base);
+                if index == 1 {
+                    // 第二个输入为封面
+                    unsafe {
+                        (*out_stream.as_mut_ptr()).disposition =
+                            rsmpeg::ffi::AV_DISPOSITION_ATTACHED_PIC as c_int;
+                    }
+                }
+                stream_index_map.insert(av_stream_ref.index as i32, out_stream.index as i32);
+            }
+            inputs.push((input_format_context, stream_index_map));
+        }
+        for (key, value) in metadata {
+            let key = CString::new(*key)?;
+            let value = CString::new(value.as_str())?;
+            unsafe {
+                rsmpeg::ffi::av_dict_set(
+                    &mut (*output_format_context.as_mut_ptr()).metadata,
+                    key.as_ptr(),
+                    value.as_ptr(),
+                    0,
+                );
+            }
+        }
+        let mut dict = None;
+        output_format_context.write_header(&mut dict)?;
+        for (mut input_format_context, stream_index_map) in inputs {
+            while let Some(mut packet) = input_format_context.read_packet()? {
+                let Some(index) = stream_index_map.get(&(packet.stream_index as i32)) else {
+                    continue;
+                };
+                packet.set_stream_index(*index as c_int);
+                output_format_context.interleaved_write_frame(&mut packet)?;
+            }
+        }
+        output_format_context.write_trailer()?;
+        Ok(())
+    }
 }
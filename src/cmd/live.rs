@@ -0,0 +1,591 @@
+use crate::cmd::out::{error, info, success, warn};
+use bbdd::live::chat::{LiveChatClient, LiveEvent};
+use bbdd::live::{FlvPacket, FlvParser, FlvTag, LiveRoomInfo, LiveSource};
+use bbdd::{BBDDError, BBDDResult};
+use std::time::{Duration, Instant};
+use tokio::fs;
+use tokio::io::{AsyncWriteExt, BufWriter};
+use tokio::sync::watch;
+
+/// 超过该时间没有收到数据时认为直播流已断开
+const READ_TIMEOUT: Duration = Duration::from_secs(30);
+/// 直播流断开后重新连接的间隔
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(3);
+/// 连续重新连接失败的次数上限
+const MAX_RECONNECT: u32 = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum LiveFormat {
+    Flv,
+    Hls,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum DanmakuFormat {
+    Xml,
+    Jsonl,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct LiveOptions {
+    pub(crate) format: LiveFormat,
+    /// 按时长切分文件
+    pub(crate) split_duration: Option<Duration>,
+    /// 按大小切分文件（字节）
+    pub(crate) split_size: Option<u64>,
+    /// 同时保存弹幕
+    pub(crate) danmaku: Option<DanmakuFormat>,
+}
+
+enum RecordEnd {
+    /// 用户按下 Ctrl-C
+    Stopped,
+    /// 直播流断开或结束
+    Ended,
+}
+
+/// 录制直播，断流时自动重新连接，按下 Ctrl-C 时写完当前数据后停止
+pub(crate) async fn record_live(room_id: i64, options: LiveOptions) -> i32 {
+    let client = super::client::CLIENT_CELL.get().unwrap();
+    let room = match client.fetch_live_room_info(room_id).await {
+        Ok(room) => room,
+        Err(err) => {
+            error(format!("无法获取直播间信息: {}", super::format_bbdd_error(&err)).as_str());
+            return 1;
+        }
+    };
+    info(format!("匹配到直播间: {} (room_id={})", room.title, room.room_id).as_str());
+    if room.live_status != bbdd::live::LIVE_STATUS_LIVE {
+        warn("直播间未开播");
+        return 1;
+    }
+    let (stop_tx, stop_rx) = watch::channel(false);
+    tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_ok() {
+            let _ = stop_tx.send(true);
+        }
+    });
+    info("开始录制，按 Ctrl-C 停止");
+    let (segment_tx, segment_rx) = watch::channel(None);
+    let danmaku = options.danmaku.map(|format| {
+        tokio::spawn(record_danmaku(
+            room.room_id,
+            format,
+            segment_rx,
+            stop_rx.clone(),
+        ))
+    });
+    let mut recorder = Recorder::new(&room, options.clone(), segment_tx);
+    let code = record_loop(&room, &mut recorder, stop_rx).await;
+    // 关闭录制后弹幕文件随之结束
+    drop(recorder);
+    if let Some(danmaku) = danmaku {
+        let _ = danmaku.await;
+    }
+    code
+}
+
+async fn record_loop(
+    room: &LiveRoomInfo,
+    recorder: &mut Recorder,
+    stop_rx: watch::Receiver<bool>,
+) -> i32 {
+    let client = super::client::CLIENT_CELL.get().unwrap();
+    let mut failures = 0;
+    loop {
+        let written = recorder.written;
+        let result = match recorder.options.format {
+            LiveFormat::Flv => record_flv(recorder, stop_rx.clone()).await,
+            LiveFormat::Hls => record_hls(recorder, stop_rx.clone()).await,
+        };
+        let finalized = recorder.finish_file().await;
+        match result {
+            Ok(RecordEnd::Stopped) => {
+                info("已停止录制");
+                return if finalized.is_ok() { 0 } else { 1 };
+            }
+            Ok(RecordEnd::Ended) => warn("直播流已断开"),
+            Err(err) => warn(format!("直播流中断: {}", super::format_bbdd_error(&err)).as_str()),
+        }
+        match client.fetch_live_room_info(room.room_id).await {
+            Ok(room) if room.live_status != bbdd::live::LIVE_STATUS_LIVE => {
+                success("直播已结束");
+                return 0;
+            }
+            Ok(_) => {}
+            Err(err) => {
+                warn(format!("无法获取直播状态: {}", super::format_bbdd_error(&err)).as_str())
+            }
+        }
+        failures = if recorder.written > written {
+            1
+        } else {
+            failures + 1
+        };
+        if failures > MAX_RECONNECT {
+            error("多次重新连接失败，停止录制");
+            return 1;
+        }
+        info(format!("{} 秒后重新连接", RECONNECT_INTERVAL.as_secs()).as_str());
+        if wait_or_stop(RECONNECT_INTERVAL, stop_rx.clone()).await {
+            info("已停止录制");
+            return 0;
+        }
+    }
+}
+
+/// 等待一段时间，期间按下 Ctrl-C 时返回 true
+async fn wait_or_stop(duration: Duration, mut stop: watch::Receiver<bool>) -> bool {
+    tokio::select! {
+        _ = stop.wait_for(|s| *s) => true,
+        _ = tokio::time::sleep(duration) => false,
+    }
+}
+
+/// 管理录制的输出文件，切分或者重新连接时创建新的文件
+struct Recorder {
+    title: String,
+    room_id: i64,
+    options: LiveOptions,
+    file: Option<OutputFile>,
+    /// 所有文件累计写入的字节数
+    written: u64,
+    /// 通知弹幕录制当前的文件
+    segment_tx: watch::Sender<Option<Segment>>,
+}
+
+/// 当前录制文件的文件名（不含扩展名）和开始时间，弹幕的时间以此为准
+#[derive(Debug, Clone)]
+struct Segment {
+    base: String,
+    started: Instant,
+}
+
+struct OutputFile {
+    name: String,
+    writer: BufWriter<fs::File>,
+    size: u64,
+    started: Instant,
+}
+
+impl Recorder {
+    fn new(
+        room: &LiveRoomInfo,
+        options: LiveOptions,
+        segment_tx: watch::Sender<Option<Segment>>,
+    ) -> Self {
+        Recorder {
+            title: room.title.clone(),
+            room_id: room.room_id,
+            options,
+            file: None,
+            written: 0,
+            segment_tx,
+        }
+    }
+
+    fn should_split(&self) -> bool {
+        let Some(file) = &self.file else {
+            return false;
+        };
+        self.options
+            .split_size
+            .is_some_and(|size| file.size >= size)
+            || self
+                .options
+                .split_duration
+                .is_some_and(|duration| file.started.elapsed() >= duration)
+    }
+
+    async fn new_file(&mut self, ext: &str) -> BBDDResult<()> {
+        self.finish_file().await?;
+        let time = chrono::Local::now().format("%Y%m%d_%H%M%S");
+        let base = super::download::file_title(
+            format!("{}_{}_{}", self.title, self.room_id, time).as_str(),
+        );
+        let mut unique = base.clone();
+        let mut index = 1;
+        while std::path::Path::new(format!("{}.{}", unique, ext).as_str()).exists() {
+            index += 1;
+            unique = format!("{}_{}", base, index);
+        }
+        let name = format!("{}.{}", unique, ext);
+        let file = fs::File::create(name.as_str())
+            .await
+            .map_err(|e| BBDDError::StateError(format!("无法创建文件 {}: {}", name, e)))?;
+        info(format!("写入文件: {}", name).as_str());
+        let started = Instant::now();
+        self.file = Some(OutputFile {
+            name,
+            writer: BufWriter::new(file),
+            size: 0,
+            started,
+        });
+        self.segment_tx.send_replace(Some(Segment {
+            base: unique,
+            started,
+        }));
+        Ok(())
+    }
+
+    async fn write(&mut self, data: &[u8]) -> BBDDResult<()> {
+        let file = self
+            .file
+            .as_mut()
+            .ok_or_else(|| BBDDError::StateError("没有打开的录制文件".to_string()))?;
+        file.writer
+            .write_all(data)
+            .await
+            .map_err(|e| BBDDError::StateError(format!("写入文件失败 {}: {}", file.name, e)))?;
+        file.size += data.len() as u64;
+        self.written += data.len() as u64;
+        Ok(())
+    }
+
+    /// 写入缓冲的数据并关闭当前文件，没有数据的文件会被删除
+    async fn finish_file(&mut self) -> BBDDResult<()> {
+        let Some(mut file) = self.file.take() else {
+            return Ok(());
+        };
+        self.segment_tx.send_replace(None);
+        let result = async {
+            file.writer.flush().await?;
+            file.writer.get_ref().sync_all().await
+        }
+        .await;
+        if let Err(e) = result {
+            error(format!("保存文件失败 {}: {}", file.name, e).as_str());
+            return Err(BBDDError::StateError(format!("保存文件失败: {}", e)));
+        }
+        if file.size == 0 {
+            let _ = fs::remove_file(file.name.as_str()).await;
+        } else {
+            success(
+                format!(
+                    "已保存: {} ({:.1} MB)",
+                    file.name,
+                    file.size as f64 / 1024.0 / 1024.0
+                )
+                .as_str(),
+            );
+        }
+        Ok(())
+    }
+}
+
+/// 按格式和编码偏好选择直播流，FLV 优先 avc，HLS 优先 fmp4
+async fn select_source(room_id: i64, format: LiveFormat) -> BBDDResult<LiveSource> {
+    let client = super::client::CLIENT_CELL.get().unwrap();
+    let play_info = client
+        .live_play_info(room_id, bbdd::live::LIVE_QN_ORIGINAL)
+        .await?;
+    let codec = super::CONFIG
+        .get()
+        .and_then(|c| c.codec.clone())
+        .unwrap_or_else(|| "avc".to_string());
+    let source = play_info
+        .sources()
+        .into_iter()
+        .filter(|s| !s.urls.is_empty())
+        .filter(|s| match format {
+            LiveFormat::Flv => s.format_name == "flv",
+            LiveFormat::Hls => s.protocol_name == "http_hls",
+        })
+        .max_by_key(|s| (s.format_name == "fmp4", s.codec_name == codec, s.qn))
+        .ok_or_else(|| BBDDError::StateError("没有可用的直播流".to_string()))?;
+    info(
+        format!(
+            "选择直播流: {} {} {}",
+            play_info.qn_desc(source.qn),
+            source.format_name,
+            source.codec_name
+        )
+        .as_str(),
+    );
+    Ok(source)
+}
+
+/// 依次尝试各个 CDN 节点
+async fn open_stream(urls: &[String]) -> BBDDResult<reqwest::Response> {
+    let client = super::client::CLIENT_CELL.get().unwrap();
+    let mut last_err = None;
+    for url in urls {
+        match client.get_live_stream(url).await {
+            Ok(response) => return Ok(response),
+            Err(e) => last_err = Some(e),
+        }
+    }
+    Err(last_err.unwrap_or_else(|| BBDDError::StateError("没有可用的直播流".to_string())))
+}
+
+/// 录制 FLV 直播流，只写入完整的 tag；切分时从关键帧开始，并重新写入文件头和解码配置
+async fn record_flv(
+    recorder: &mut Recorder,
+    mut stop: watch::Receiver<bool>,
+) -> BBDDResult<RecordEnd> {
+    let source = select_source(recorder.room_id, LiveFormat::Flv).await?;
+    let mut response = open_stream(&source.urls).await?;
+    let mut parser = FlvParser::default();
+    let mut header: Option<Vec<u8>> = None;
+    // 脚本 tag 和音视频的解码配置
+    let mut sequence_tags: Vec<FlvTag> = vec![];
+    let mut base_timestamp = 0;
+    loop {
+        let chunk = tokio::select! {
+            _ = stop.wait_for(|s| *s) => return Ok(RecordEnd::Stopped),
+            chunk = tokio::time::timeout(READ_TIMEOUT, response.chunk()) => chunk
+                .map_err(|_| BBDDError::StateError("读取直播流超时".to_string()))??,
+        };
+        let Some(chunk) = chunk else {
+            return Ok(RecordEnd::Ended);
+        };
+        for packet in parser.push(&chunk)? {
+            let mut tag = match packet {
+                FlvPacket::Header(bytes) => {
+                    header = Some(bytes);
+                    continue;
+                }
+                FlvPacket::Tag(tag) => tag,
+            };
+            let is_config =
+                tag.is_sequence_header() || tag.tag_type() == bbdd::live::FLV_TAG_SCRIPT;
+            if is_config {
+                sequence_tags.retain(|t| t.tag_type() != tag.tag_type());
+                sequence_tags.push(tag.clone());
+            }
+            if recorder.file.is_none() || (recorder.should_split() && tag.is_keyframe()) {
+                if is_config {
+                    continue;
+                }
+                recorder.new_file("flv").await?;
+                let header = header
+                    .as_ref()
+                    .ok_or_else(|| BBDDError::StateError("直播流缺少FLV文件头".to_string()))?;
+                recorder.write(header).await?;
+                for sequence_tag in &sequence_tags {
+                    let mut sequence_tag = sequence_tag.clone();
+                    sequence_tag.set_timestamp(0);
+                    recorder.write(&sequence_tag.bytes).await?;
+                }
+                base_timestamp = tag.timestamp();
+            }
+            tag.set_timestamp(tag.timestamp().saturating_sub(base_timestamp));
+            recorder.write(&tag.bytes).await?;
+        }
+    }
+}
+
+/// 录制 HLS 直播流，定时刷新播放列表并下载新的分片；fmp4 的每个文件开头写入初始化分片
+async fn record_hls(
+    recorder: &mut Recorder,
+    mut stop: watch::Receiver<bool>,
+) -> BBDDResult<RecordEnd> {
+    let client = super::client::CLIENT_CELL.get().unwrap();
+    let source = select_source(recorder.room_id, LiveFormat::Hls).await?;
+    let ext = if source.format_name == "fmp4" {
+        "mp4"
+    } else {
+        "ts"
+    };
+    let mut playlist_url = source.urls.first().cloned().unwrap_or_default();
+    let mut last_sequence: Option<u64> = None;
+    let mut init: Option<(String, Vec<u8>)> = None;
+    let mut last_update = Instant::now();
+    loop {
+        let response = tokio::select! {
+            _ = stop.wait_for(|s| *s) => return Ok(RecordEnd::Stopped),
+            response = open_stream(std::slice::from_ref(&playlist_url)) => response?,
+        };
+        // 之后的请求直接使用重定向后的地址
+        let base = response.url().clone();
+        playlist_url = base.to_string();
+        let playlist = bbdd::live::parse_m3u8(response.text().await?.as_str())?;
+        if let Some(map_uri) = &playlist.map_uri
+            && init.as_ref().is_none_or(|(uri, _)| uri != map_uri)
+        {
+            let url = join_url(&base, map_uri)?;
+            let bytes = client.get_live_stream(url.as_str()).await?.bytes().await?;
+            init = Some((map_uri.clone(), bytes.to_vec()));
+            // 初始化分片变化后之前的文件无法继续使用
+            recorder.finish_file().await?;
+        }
+        for segment in &playlist.segments {
+            if last_sequence.is_some_and(|last| segment.sequence <= last) {
+                continue;
+            }
+            let url = join_url(&base, &segment.uri)?;
+            let bytes = tokio::select! {
+                _ = stop.wait_for(|s| *s) => return Ok(RecordEnd::Stopped),
+                response = client.get_live_stream(url.as_str()) => match response {
+                    Ok(response) => response.bytes().await?,
+                    Err(e) => {
+                        warn(format!("分片 {} 下载失败，跳过: {}", segment.sequence, e).as_str());
+                        last_sequence = Some(segment.sequence);
+                        continue;
+                    }
+                },
+            };
+            if recorder.file.is_none() || recorder.should_split() {
+                recorder.new_file(ext).await?;
+                if let Some((_, init)) = &init {
+                    recorder.write(init).await?;
+                }
+            }
+            recorder.write(&bytes).await?;
+            last_sequence = Some(segment.sequence);
+            last_update = Instant::now();
+        }
+        if playlist.ended || last_update.elapsed() > READ_TIMEOUT {
+            return Ok(RecordEnd::Ended);
+        }
+        let interval = Duration::from_secs_f64((playlist.target_duration / 2.0).clamp(0.5, 5.0));
+        if wait_or_stop(interval, stop.clone()).await {
+            return Ok(RecordEnd::Stopped);
+        }
+    }
+}
+
+fn join_url(base: &reqwest::Url, uri: &str) -> BBDDResult<String> {
+    base.join(uri)
+        .map(|u| u.to_string())
+        .map_err(|e| BBDDError::StateError(format!("分片地址错误 {}: {}", uri, e)))
+}
+
+/// 录制弹幕，与录制的视频文件一一对应，时间为相对于视频文件开始的秒数；连接断开时自动重新连接
+async fn record_danmaku(
+    room_id: i64,
+    format: DanmakuFormat,
+    mut segments: watch::Receiver<Option<Segment>>,
+    mut stop: watch::Receiver<bool>,
+) {
+    let mut chat: Option<LiveChatClient> = None;
+    let mut writer: Option<DanmakuWriter> = None;
+    let mut connected = true;
+    loop {
+        if !connected && wait_or_stop(RECONNECT_INTERVAL, stop.clone()).await {
+            break;
+        }
+        connected = true;
+        tokio::select! {
+            // 不能在其他分支的 await 中持有 watch::Ref
+            _ = async { stop.wait_for(|s| *s).await.is_ok() } => break,
+            changed = segments.changed() => {
+                if let Some(writer) = writer.take() {
+                    writer.finish().await;
+                }
+                if changed.is_err() {
+                    break;
+                }
+                let segment = segments.borrow_and_update().clone();
+                if let Some(segment) = segment {
+                    writer = DanmakuWriter::create(segment, format).await;
+                }
+            }
+            event = next_chat_event(&mut chat, room_id) => match event {
+                Ok(Some(event)) => {
+                    if let Some(writer) = writer.as_mut() {
+                        writer.write(&event).await;
+                    }
+                }
+                Ok(None) | Err(_) => {
+                    if let Err(err) = event {
+                        warn(format!("弹幕连接中断: {}", super::format_bbdd_error(&err)).as_str());
+                    }
+                    chat = None;
+                    connected = false;
+                }
+            },
+        }
+    }
+    if let Some(writer) = writer.take() {
+        writer.finish().await;
+    }
+}
+
+async fn next_chat_event(
+    chat: &mut Option<LiveChatClient>,
+    room_id: i64,
+) -> BBDDResult<Option<LiveEvent>> {
+    if chat.is_none() {
+        let client = super::client::CLIENT_CELL.get().unwrap();
+        *chat = Some(client.connect_live_chat(room_id).await?);
+        info("已连接弹幕服务器");
+    }
+    chat.as_mut().unwrap().next_event().await
+}
+
+struct DanmakuWriter {
+    name: String,
+    writer: BufWriter<fs::File>,
+    started: Instant,
+    format: DanmakuFormat,
+}
+
+impl DanmakuWriter {
+    async fn create(segment: Segment, format: DanmakuFormat) -> Option<DanmakuWriter> {
+        let ext = match format {
+            DanmakuFormat::Xml => "xml",
+            DanmakuFormat::Jsonl => "jsonl",
+        };
+        let name = format!("{}.{}", segment.base, ext);
+        let file = match fs::File::create(name.as_str()).await {
+            Ok(file) => file,
+            Err(e) => {
+                warn(format!("无法创建弹幕文件 {}: {}", name, e).as_str());
+                return None;
+            }
+        };
+        let mut writer = DanmakuWriter {
+            name,
+            writer: BufWriter::new(file),
+            started: segment.started,
+            format,
+        };
+        if format == DanmakuFormat::Xml {
+            writer
+                .write_line("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<i>\n<chatserver>chat.bilibili.com</chatserver>\n<chatid>0</chatid>")
+                .await;
+        }
+        Some(writer)
+    }
+
+    /// 只保存弹幕、礼物和醒目留言
+    async fn write(&mut self, event: &LiveEvent) {
+        let offset = self.started.elapsed().as_secs_f64();
+        let line = match self.format {
+            DanmakuFormat::Xml => event.to_xml(offset),
+            DanmakuFormat::Jsonl => match event {
+                LiveEvent::Danmaku(_) | LiveEvent::Gift(_) | LiveEvent::SuperChat(_) => {
+                    let mut json = serde_json::to_value(event).unwrap_or_default();
+                    json["offset"] = serde_json::json!((offset * 1000.0).round() / 1000.0);
+                    Some(json.to_string())
+                }
+                _ => None,
+            },
+        };
+        if let Some(line) = line {
+            self.write_line(line.as_str()).await;
+        }
+    }
+
+    async fn write_line(&mut self, line: &str) {
+        let result = async {
+            self.writer.write_all(line.as_bytes()).await?;
+            self.writer.write_all(b"\n").await
+        }
+        .await;
+        if let Err(e) = result {
+            warn(format!("写入弹幕文件失败 {}: {}", self.name, e).as_str());
+        }
+    }
+
+    async fn finish(mut self) {
+        if self.format == DanmakuFormat::Xml {
+            self.write_line("</i>").await;
+        }
+        match self.writer.flush().await {
+            Ok(_) => success(format!("弹幕已保存: {}", self.name).as_str()),
+            Err(e) => warn(format!("保存弹幕文件失败 {}: {}", self.name, e).as_str()),
+        }
+    }
+}
@@ -1,9 +1,12 @@
 use super::out::*;
 use std::process::exit;
 
-pub(crate) async fn login() {
+pub(crate) async fn login(matches: &clap::ArgMatches) {
     let client = crate::cmd::client::CLIENT_CELL.get().unwrap();
-    if !client.cookie.is_empty() {
+    if let Some(file) = matches.get_one::<String>("cookies") {
+        return login_with_cookies(file).await;
+    }
+    if client.has_cookie("SESSDATA") {
         let confirm = dialoguer::Confirm::new()
             .with_prompt("检测到已有登录信息，是否覆盖？")
             .interact()
@@ -13,20 +16,33 @@ pub(crate) async fn login() {
             return;
         }
     }
-    let client = crate::cmd::client::CLIENT_CELL.get().unwrap();
-    let url = client.web_login_qr_url().await.expect("获取登录二维码失败");
-    if let Err(e) = qr2term::print_qr(url.url.as_str()) {
-        warn(format!("生成二维码失败: {}", e).as_str());
+    if matches.get_flag("sms") {
+        login_with_sms().await
+    } else if matches.get_flag("password") {
+        login_with_password().await
+    } else if matches.get_flag("tv") {
+        login_with_tv().await
+    } else {
+        login_with_qr().await
     }
-    info(format!("登录二维码链接: {}", url.url).as_str());
-    info("请使用B站APP扫码登录，扫码后请等待几秒钟...");
+}
+
+async fn login_with_qr() {
+    use bbdd::auth::web::WebLoginQRState;
+    use futures::StreamExt;
+    let client = crate::cmd::client::CLIENT_CELL.get().unwrap();
+    let stream = client.web_login_qr_stream(std::time::Duration::from_secs(5));
+    futures::pin_mut!(stream);
     let mut consecutive_errors: u32 = 0;
-    loop {
-        tokio::time::sleep(std::time::Duration::from_secs(5)).await;
-        let verify = match client.web_login_qr_verify(&url.qrcode_key).await {
-            Ok(v) => {
+    while let Some(state) = stream.next().await {
+        let state = match state {
+            Ok(state) => {
                 consecutive_errors = 0;
-                v
+                state
+            }
+            Err(e @ bbdd::BBDDError::ApiError { .. }) => {
+                error(format!("登录失败: {}", super::format_bbdd_error(&e)).as_str());
+                exit(1);
             }
             Err(e) => {
                 consecutive_errors = consecutive_errors.saturating_add(1);
@@ -45,18 +61,185 @@ pub(crate) async fn login() {
                 continue;
             }
         };
-        // 86101: 二维码未扫描
-        // 86090: 已扫描未确认
-        // 86038: 二维码已失效
-        if verify.code == 86101 || verify.code == 86090 {
-            continue;
+        match state {
+            WebLoginQRState::Generated(url) => {
+                if let Err(e) = qr2term::print_qr(url.url.as_str()) {
+                    warn(format!("生成二维码失败: {}", e).as_str());
+                }
+                info(format!("登录二维码链接: {}", url.url).as_str());
+                info("请使用B站APP扫码登录，扫码后请等待几秒钟...");
+            }
+            WebLoginQRState::Waiting => {}
+            WebLoginQRState::Scanned => info("已扫码，请在APP上确认登录"),
+            WebLoginQRState::Expired => warn("二维码已失效，重新生成二维码"),
+            WebLoginQRState::Success(verify) => {
+                super::error_exit(crate::cmd::client::store_login(&verify));
+                success("登录成功");
+                return;
+            }
         }
-        if verify.code == 0 {
-            crate::cmd::client::store_login(&verify);
-            break;
+    }
+    error("获取登录二维码失败");
+    exit(1);
+}
+
+async fn login_with_cookies(file: &str) {
+    let client = crate::cmd::client::CLIENT_CELL.get().unwrap();
+    let text = match std::fs::read_to_string(file) {
+        Ok(text) => text,
+        Err(e) => {
+            error(format!("无法读取cookie文件 {}: {}", file, e).as_str());
+            exit(1);
+        }
+    };
+    let jar = match bbdd::CookieJar::import(&text) {
+        Ok(jar) => jar,
+        Err(e) => {
+            error(format!("cookie文件格式错误: {}", super::format_bbdd_error(&e)).as_str());
+            exit(1);
+        }
+    };
+    if jar.get("SESSDATA").is_none() {
+        warn("导入的cookie中没有SESSDATA，可能无法登录");
+    }
+    // 先替换 cookie 再用 web_nav 验证，验证失败时恢复原来的 cookie
+    let previous = client.cookie_jar();
+    if let Err(e) = client.set_cookie_jar(jar) {
+        error(super::format_bbdd_error(&e).as_str());
+        exit(1);
+    }
+    let nav = match client.web_nav().await {
+        Ok(nav) if nav.is_login => nav,
+        Ok(_) => {
+            let _ = client.set_cookie_jar(previous);
+            error("导入的cookie已失效或不完整，未能登录");
+            exit(1);
         }
-        error(format!("登录失败: {} ({})", verify.message, verify.code).as_str());
+        Err(e) if e.is_login_required() => {
+            let _ = client.set_cookie_jar(previous);
+            error("导入的cookie已失效或不完整，未能登录");
+            exit(1);
+        }
+        Err(e) => {
+            let _ = client.set_cookie_jar(previous);
+            error(format!("验证登录状态失败: {}", super::format_bbdd_error(&e)).as_str());
+            exit(1);
+        }
+    };
+    crate::cmd::client::clear_web_login();
+    success(format!("登录成功: {} (mid={})", nav.uname, nav.mid).as_str());
+    if !client.has_cookie("bili_jct") {
+        warn("导入的cookie中没有bili_jct，将无法自动刷新登录状态");
+    }
+}
+
+/// 在终端中提示用户完成极验验证
+struct TerminalCaptchaSolver;
+
+impl bbdd::auth::captcha::CaptchaSolver for TerminalCaptchaSolver {
+    async fn solve(
+        &self,
+        captcha: &bbdd::auth::captcha::WebCaptchaData,
+    ) -> bbdd::BBDDResult<bbdd::auth::captcha::CaptchaResult> {
+        info(
+            "登录需要完成极验验证，请使用极验验证工具（例如 https://kuresaru.github.io/geetest-validator/ ）输入以下参数完成验证",
+        );
+        info(format!("gt: {}", captcha.geetest.gt).as_str());
+        info(format!("challenge: {}", captcha.geetest.challenge).as_str());
+        let validate: String = prompt("validate", None)?;
+        let challenge: String = prompt("challenge", Some(captcha.geetest.challenge.clone()))?;
+        Ok(bbdd::auth::captcha::CaptchaResult::new(
+            captcha, &challenge, &validate,
+        ))
+    }
+}
+
+fn prompt(text: &str, default: Option<String>) -> bbdd::BBDDResult<String> {
+    let mut input = dialoguer::Input::<String>::new().with_prompt(text);
+    if let Some(default) = default {
+        input = input.default(default);
+    }
+    input
+        .interact_text()
+        .map(|v| v.trim().to_string())
+        .map_err(|e| bbdd::BBDDError::StateError(format!("读取输入失败: {}", e)))
+}
+
+async fn login_with_sms() {
+    let client = crate::cmd::client::CLIENT_CELL.get().unwrap();
+    let cid = super::error_exit(prompt("国际区号", Some("86".to_string())));
+    let Ok(cid) = cid.trim_start_matches('+').parse::<i64>() else {
+        error("国际区号必须是数字");
         exit(1);
+    };
+    let tel = super::error_exit(prompt("手机号", None));
+    let captcha_key = super::error_exit(
+        client
+            .web_sms_send_with(cid, &tel, &TerminalCaptchaSolver)
+            .await,
+    );
+    success("验证码已发送");
+    let code = super::error_exit(prompt("短信验证码", None));
+    let data = super::error_exit(client.web_login_sms(cid, &tel, &code, &captcha_key).await);
+    super::error_exit(crate::cmd::client::store_login(&data.into()));
+    success("登录成功")
+}
+
+async fn login_with_password() {
+    let client = crate::cmd::client::CLIENT_CELL.get().unwrap();
+    let username = super::error_exit(prompt("账号", None));
+    let password = match dialoguer::Password::new().with_prompt("密码").interact() {
+        Ok(password) => password,
+        Err(e) => {
+            error(format!("读取输入失败: {}", e).as_str());
+            exit(1);
+        }
+    };
+    let data = super::error_exit(
+        client
+            .web_login_password_with(&username, &password, &TerminalCaptchaSolver)
+            .await,
+    );
+    super::error_exit(crate::cmd::client::store_login(&data.into()));
+    success("登录成功")
+}
+
+/// TV 端扫码登录，除了 web cookie 之外还会得到 APP 接口使用的 access_key
+async fn login_with_tv() {
+    use bbdd::auth::tv::{TV_QR_CODE_EXPIRED, TV_QR_CODE_SCANNED, TV_QR_CODE_WAITING};
+    let client = crate::cmd::client::CLIENT_CELL.get().unwrap();
+    let qr = super::error_exit(client.tv_login_qr_url().await);
+    if let Err(e) = qr2term::print_qr(qr.url.as_str()) {
+        warn(format!("生成二维码失败: {}", e).as_str());
+    }
+    info(format!("登录二维码链接: {}", qr.url).as_str());
+    info("请使用B站APP扫码登录，扫码后请等待几秒钟...");
+    loop {
+        tokio::time::sleep(std::time::Duration::from_secs(5)).await;
+        match client.tv_login_qr_verify(&qr.auth_code).await {
+            Ok(data) => {
+                crate::cmd::client::store_tv_login(&data);
+                // web_login.json 中的 refresh_token 属于之前的会话
+                crate::cmd::client::clear_web_login();
+                break;
+            }
+            Err(bbdd::BBDDError::ApiError { code, .. })
+                if code == TV_QR_CODE_WAITING || code == TV_QR_CODE_SCANNED => {}
+            Err(bbdd::BBDDError::ApiError {
+                code: TV_QR_CODE_EXPIRED,
+                ..
+            }) => {
+                error("登录失败: 二维码已失效");
+                exit(1);
+            }
+            Err(bbdd::BBDDError::ApiError { code, message }) => {
+                error(format!("登录失败: {} ({})", message, code).as_str());
+                exit(1);
+            }
+            Err(e) => {
+                warn(format!("登录轮询请求失败(将重试): {}", super::format_bbdd_error(&e)).as_str())
+            }
+        }
     }
     success("登录成功")
 }
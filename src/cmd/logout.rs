@@ -0,0 +1,32 @@
+use super::out::*;
+use std::process::exit;
+
+pub(crate) async fn logout(matches: &clap::ArgMatches) {
+    let client = crate::cmd::client::CLIENT_CELL.get().unwrap();
+    if !client.has_cookie("SESSDATA") {
+        crate::cmd::client::clear_web_login();
+        crate::cmd::client::clear_tv_login();
+        warn("当前未登录");
+        return;
+    }
+    if let Err(e) = client.web_logout().await {
+        if !matches.get_flag("local") {
+            error(
+                format!(
+                    "退出登录失败: {}，可使用 --local 只删除本地登录信息",
+                    super::format_bbdd_error(&e)
+                )
+                .as_str(),
+            );
+            exit(1);
+        }
+        warn(format!("服务端退出登录失败: {}", super::format_bbdd_error(&e)).as_str());
+        if let Err(e) = client.set_cookie_jar(bbdd::CookieJar::default()) {
+            error(super::format_bbdd_error(&e).as_str());
+            exit(1);
+        }
+    }
+    crate::cmd::client::clear_web_login();
+    crate::cmd::client::clear_tv_login();
+    success("已退出登录");
+}
@@ -0,0 +1,139 @@
+use super::out::{error, info, success, warn};
+use bbdd::{BBDDError, BBDDResult};
+use std::path::Path;
+use std::process::exit;
+
+/// 默认账号，登录信息直接保存在配置目录下，兼容之前的版本
+pub(crate) const DEFAULT_PROFILE: &str = "default";
+
+/// 账号名只能包含字母、数字、下划线和中划线
+pub(crate) fn validate_name(name: &str) -> BBDDResult<()> {
+    if !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    {
+        Ok(())
+    } else {
+        Err(BBDDError::ParamError(format!(
+            "账号名无效: {}，只能包含字母、数字、下划线和中划线",
+            name
+        )))
+    }
+}
+
+/// 账号的 cookie、UA 等文件所在目录，其它账号保存在配置目录的 profiles/<name> 下
+pub(crate) fn profile_dir(config_dir: &str, name: &str) -> String {
+    if name == DEFAULT_PROFILE {
+        config_dir.to_string()
+    } else {
+        super::local::join_paths(vec![config_dir, "profiles", name])
+    }
+}
+
+pub(crate) fn profile_exists(config_dir: &str, name: &str) -> bool {
+    name == DEFAULT_PROFILE || Path::new(profile_dir(config_dir, name).as_str()).is_dir()
+}
+
+/// 默认账号在前，其余按名称排序
+pub(crate) fn list_profiles(config_dir: &str) -> Vec<String> {
+    let mut names = vec![];
+    let dir = super::local::join_paths(vec![config_dir, "profiles"]);
+    if let Ok(entries) = std::fs::read_dir(dir) {
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if entry.path().is_dir() && name != DEFAULT_PROFILE && validate_name(&name).is_ok() {
+                names.push(name);
+            }
+        }
+    }
+    names.sort();
+    names.insert(0, DEFAULT_PROFILE.to_string());
+    names
+}
+
+/// 读取账号保存的 cookie，不发送请求
+fn local_cookie_jar(dir: &Path) -> bbdd::CookieJar {
+    let cookie_path = dir.join("cookies.json");
+    let source = if cookie_path.exists() {
+        bbdd::CookieSource::JarFile(cookie_path)
+    } else {
+        bbdd::CookieSource::WebLoginFile(dir.join("web_login.json"))
+    };
+    source.load().unwrap_or_default()
+}
+
+/// 删除账号的登录信息，默认账号只删除登录文件，其它账号删除整个目录
+fn remove_profile(config_dir: &str, name: &str) -> std::io::Result<()> {
+    let dir = profile_dir(config_dir, name);
+    if name != DEFAULT_PROFILE {
+        return std::fs::remove_dir_all(dir);
+    }
+    for file in ["cookies.json", "web_login.json", "tv_login.json"] {
+        let path = Path::new(dir.as_str()).join(file);
+        if path.exists() {
+            std::fs::remove_file(path)?;
+        }
+    }
+    Ok(())
+}
+
+pub(crate) fn accounts_command(config_dir: &str, current: &str, matches: &clap::ArgMatches) {
+    match matches.subcommand() {
+        Some(("remove", sub)) => {
+            let name = sub.get_one::<String>("name").unwrap();
+            if let Err(e) = validate_name(name) {
+                error(super::format_bbdd_error(&e).as_str());
+                exit(1);
+            }
+            if !profile_exists(config_dir, name) {
+                error(format!("账号不存在: {}", name).as_str());
+                exit(1);
+            }
+            if !sub.get_flag("yes") {
+                let confirm = dialoguer::Confirm::new()
+                    .with_prompt(format!("确定删除账号 {} 的登录信息吗？", name))
+                    .interact()
+                    .unwrap_or(false);
+                if !confirm {
+                    warn("取消删除");
+                    return;
+                }
+            }
+            if let Err(e) = remove_profile(config_dir, name) {
+                error(format!("删除账号失败: {}", e).as_str());
+                exit(1);
+            }
+            success(format!("已删除账号: {}", name).as_str());
+        }
+        _ => {
+            for name in list_profiles(config_dir) {
+                let mark = if name == current { "*" } else { " " };
+                let dir = profile_dir(config_dir, &name);
+                let jar = local_cookie_jar(Path::new(dir.as_str()));
+                let state = match jar.get("SESSDATA") {
+                    None => "未登录".to_string(),
+                    Some(sessdata) => {
+                        let mid = jar
+                            .get("DedeUserID")
+                            .map(|c| format!(" mid={}", c.value))
+                            .unwrap_or_default();
+                        let expires = sessdata
+                            .expires
+                            .and_then(|e| chrono::DateTime::from_timestamp(e, 0))
+                            .map(|e| {
+                                format!(
+                                    " 有效期至 {}",
+                                    e.with_timezone(&chrono::Local).format("%Y-%m-%d")
+                                )
+                            })
+                            .unwrap_or_default();
+                        format!("已保存登录信息{}{}", mid, expires)
+                    }
+                };
+                println!("{} {} ({})", mark, name, state);
+            }
+            info("使用 --profile <NAME> 切换账号，bbdd login --profile <NAME> 添加账号");
+        }
+    }
+}
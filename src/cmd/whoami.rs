@@ -2,7 +2,7 @@ use super::out::{info, success, warn};
 
 pub(crate) async fn whoami() {
     let client = crate::cmd::client::CLIENT_CELL.get().unwrap();
-    if client.cookie.is_empty() {
+    if !client.has_cookie("SESSDATA") {
         warn("当前未加载到 cookie（未登录或未保存登录信息）");
     }
 
@@ -16,8 +16,8 @@ pub(crate) async fn whoami() {
 
     if !nav.is_login {
         warn("当前账号状态: 未登录");
-        info("可运行 `bbdd login` 重新登录（或检查本地 web_login.json 是否有效）");
-        print_cookie_hints(client.cookie.as_str());
+        info("可运行 `bbdd login` 重新登录（或检查本地 cookies.json 是否有效）");
+        print_cookie_hints(&client.cookie_jar());
         return;
     }
 
@@ -29,11 +29,48 @@ pub(crate) async fn whoami() {
         info("会员: 否");
     }
     info(format!("余额: {}", nav.money).as_str());
-    print_cookie_hints(client.cookie.as_str());
+    if client.access_key().is_some() {
+        info("已保存TV端登录的 access_key，可以使用APP接口");
+    }
+    print_cookie_hints(&client.cookie_jar());
+}
+
+/// 显示所有账号的登录状态，当前账号前标记 *
+pub(crate) async fn whoami_all(config_dir: &str, current: &str) {
+    for name in super::profile::list_profiles(config_dir) {
+        let mark = if name == current { "*" } else { " " };
+        let dir = super::profile::profile_dir(config_dir, &name);
+        let client = super::client::build_client(std::path::Path::new(dir.as_str()));
+        if !client.has_cookie("SESSDATA") {
+            println!("{} {}: 未登录", mark, name);
+            continue;
+        }
+        match client.web_nav().await {
+            Ok(nav) if nav.is_login => {
+                let vip = if nav.vip.status == 1 {
+                    nav.vip.label.text.clone()
+                } else {
+                    "否".to_string()
+                };
+                println!(
+                    "{} {}: 已登录 {} (mid={}) 会员: {}",
+                    mark, name, nav.uname, nav.mid, vip
+                );
+            }
+            Ok(_) => println!("{} {}: 登录已失效", mark, name),
+            Err(e) if e.is_login_required() => println!("{} {}: 登录已失效", mark, name),
+            Err(e) => println!(
+                "{} {}: 认证请求失败 {}",
+                mark,
+                name,
+                super::format_bbdd_error(&e)
+            ),
+        }
+    }
 }
 
-fn print_cookie_hints(cookie: &str) {
-    if cookie.is_empty() {
+fn print_cookie_hints(jar: &bbdd::CookieJar) {
+    if jar.is_empty() {
         return;
     }
     let missing = [
@@ -42,7 +79,7 @@ fn print_cookie_hints(cookie: &str) {
         ("DedeUserID", "账号标识"),
     ]
     .into_iter()
-    .filter(|(k, _)| !cookie.contains(&format!("{k}=")))
+    .filter(|(k, _)| jar.get(k).is_none())
     .collect::<Vec<_>>();
     if !missing.is_empty() {
         warn(
@@ -57,5 +94,20 @@ fn print_cookie_hints(cookie: &str) {
             .as_str(),
         );
     }
+    if let Some(expires) = jar
+        .get("SESSDATA")
+        .and_then(|c| c.expires)
+        .and_then(|e| chrono::DateTime::from_timestamp(e, 0))
+    {
+        info(
+            format!(
+                "登录有效期至: {}",
+                expires
+                    .with_timezone(&chrono::Local)
+                    .format("%Y-%m-%d %H:%M:%S")
+            )
+            .as_str(),
+        );
+    }
 }
 
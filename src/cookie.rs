@@ -0,0 +1,468 @@
+use crate::{Error, Result};
+use serde::{Deserialize, Serialize};
+
+/// 未指定域名时使用的默认域名
+pub const DEFAULT_COOKIE_DOMAIN: &str = "bilibili.com";
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct Cookie {
+    pub name: String,
+    pub value: String,
+    /// 不带前导点的域名
+    pub domain: String,
+    /// 为 true 时只发送给与 domain 完全相同的主机
+    #[serde(default)]
+    pub host_only: bool,
+    #[serde(default = "default_path")]
+    pub path: String,
+    /// 过期时间（unix 秒），None 表示会话 cookie
+    #[serde(default)]
+    pub expires: Option<i64>,
+    #[serde(default)]
+    pub secure: bool,
+    #[serde(default)]
+    pub http_only: bool,
+}
+
+fn default_path() -> String {
+    "/".to_string()
+}
+
+impl Cookie {
+    pub fn new(name: impl Into<String>, value: impl Into<String>, domain: &str) -> Cookie {
+        Cookie {
+            name: name.into(),
+            value: value.into(),
+            domain: domain.trim_start_matches('.').to_ascii_lowercase(),
+            host_only: false,
+            path: default_path(),
+            expires: None,
+            secure: false,
+            http_only: false,
+        }
+    }
+
+    /// 解析响应中的 Set-Cookie，request_host 用于没有 Domain 属性的 cookie。
+    /// Domain 与 request_host 不匹配时（例如 bilibili.tv 设置 bilibili.com 的 cookie）忽略该 cookie
+    pub fn parse_set_cookie(header: &str, request_host: &str) -> Option<Cookie> {
+        let mut parts = header.split(';');
+        let (name, value) = parts.next()?.split_once('=')?;
+        let name = name.trim();
+        if name.is_empty() {
+            return None;
+        }
+        let mut cookie = Cookie::new(name, value.trim(), request_host);
+        cookie.host_only = true;
+        let mut max_age = None;
+        for attr in parts {
+            let (key, val) = match attr.split_once('=') {
+                Some((key, val)) => (key.trim(), val.trim()),
+                None => (attr.trim(), ""),
+            };
+            match key.to_ascii_lowercase().as_str() {
+                "domain" if !val.is_empty() => {
+                    let domain = val.trim_start_matches('.').to_ascii_lowercase();
+                    if request_host != domain
+                        && !request_host.ends_with(format!(".{}", domain).as_str())
+                    {
+                        return None;
+                    }
+                    cookie.domain = domain;
+                    cookie.host_only = false;
+                }
+                "path" if val.starts_with('/') => cookie.path = val.to_string(),
+                "expires" => cookie.expires = parse_http_date(val),
+                "max-age" => max_age = val.parse::<i64>().ok(),
+                "secure" => cookie.secure = true,
+                "httponly" => cookie.http_only = true,
+                _ => {}
+            }
+        }
+        // Max-Age 优先于 Expires
+        if let Some(max_age) = max_age {
+            cookie.expires = Some(chrono::Utc::now().timestamp() + max_age);
+        }
+        Some(cookie)
+    }
+
+    pub fn is_expired(&self, now: i64) -> bool {
+        matches!(self.expires, Some(expires) if expires <= now)
+    }
+
+    fn matches(&self, host: &str, path: &str, https: bool) -> bool {
+        if self.secure && !https {
+            return false;
+        }
+        let domain_match = if self.host_only {
+            host == self.domain
+        } else {
+            host == self.domain || host.ends_with(format!(".{}", self.domain).as_str())
+        };
+        domain_match && path.starts_with(self.path.as_str())
+    }
+
+    fn same_key(&self, other: &Cookie) -> bool {
+        self.name == other.name && self.domain == other.domain && self.path == other.path
+    }
+}
+
+fn parse_http_date(value: &str) -> Option<i64> {
+    if let Ok(date) = chrono::DateTime::parse_from_rfc2822(value) {
+        return Some(date.timestamp());
+    }
+    // 兼容 `Wed, 21-Oct-2026 07:28:00 GMT` 格式
+    chrono::NaiveDateTime::parse_from_str(value, "%a, %d-%b-%Y %H:%M:%S GMT")
+        .ok()
+        .map(|date| date.and_utc().timestamp())
+}
+
+/// 按域名保存的 cookie 集合，可以序列化为 json 持久化
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct CookieJar {
+    #[serde(default)]
+    pub cookies: Vec<Cookie>,
+}
+
+impl CookieJar {
+    /// 解析 `a=b; c=d` 形式的 Cookie 请求头
+    pub fn parse_header(header: &str, domain: &str) -> CookieJar {
+        let mut jar = CookieJar::default();
+        for pair in header.split(';') {
+            if let Some((name, value)) = pair.split_once('=') {
+                let name = name.trim();
+                if !name.is_empty() {
+                    jar.insert(Cookie::new(name, value.trim(), domain));
+                }
+            }
+        }
+        jar
+    }
+
+    /// 解析扫码登录成功后返回的 crossDomain 链接，Expires 参数作为所有 cookie 的过期时间
+    pub fn from_cross_domain_url(url: &str) -> Result<CookieJar> {
+        let (_, query) = url.split_once('?').ok_or(Error::ParamError(
+            "Invalid URL, missing query parameters".to_string(),
+        ))?;
+        let mut expires = None;
+        let mut cookies = vec![];
+        for pair in query.split('&') {
+            let Some((name, value)) = pair.split_once('=') else {
+                continue;
+            };
+            match name {
+                "Expires" => expires = value.parse::<i64>().ok(),
+                "gourl" | "first_domain" => {}
+                _ => cookies.push(Cookie::new(
+                    name,
+                    value.replace(",", "%2C"),
+                    DEFAULT_COOKIE_DOMAIN,
+                )),
+            }
+        }
+        let mut jar = CookieJar::default();
+        for mut cookie in cookies {
+            cookie.expires = expires;
+            jar.insert(cookie);
+        }
+        Ok(jar)
+    }
+
+    /// 导入浏览器导出的 cookie，自动识别 JSON、Netscape cookies.txt 和 `Cookie:` 请求头格式
+    pub fn import(text: &str) -> Result<CookieJar> {
+        let text = text.trim_start_matches('\u{feff}').trim();
+        let jar = if text.starts_with('{') || text.starts_with('[') {
+            CookieJar::parse_json(text)?
+        } else if text.lines().any(|line| line.split('\t').count() >= 7) {
+            CookieJar::parse_netscape(text)
+        } else {
+            let header = text
+                .strip_prefix("Cookie:")
+                .or_else(|| text.strip_prefix("cookie:"))
+                .unwrap_or(text);
+            CookieJar::parse_header(header.trim(), DEFAULT_COOKIE_DOMAIN)
+        };
+        if jar.is_empty() {
+            return Err(Error::ParamError("没有解析到任何 cookie".to_string()));
+        }
+        Ok(jar)
+    }
+
+    /// 解析 Netscape 格式的 cookies.txt，`#HttpOnly_` 前缀表示 HttpOnly
+    pub fn parse_netscape(text: &str) -> CookieJar {
+        let mut jar = CookieJar::default();
+        for line in text.lines() {
+            let (line, http_only) = match line.strip_prefix("#HttpOnly_") {
+                Some(line) => (line, true),
+                None => (line, false),
+            };
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let fields: Vec<&str> = line.split('\t').collect();
+            if fields.len() < 7 || fields[5].is_empty() {
+                continue;
+            }
+            let mut cookie = Cookie::new(fields[5], fields[6].trim_end_matches('\r'), fields[0]);
+            cookie.host_only = !fields[1].eq_ignore_ascii_case("TRUE");
+            if fields[2].starts_with('/') {
+                cookie.path = fields[2].to_string();
+            }
+            cookie.secure = fields[3].eq_ignore_ascii_case("TRUE");
+            // 0 表示会话 cookie
+            cookie.expires = fields[4].parse::<i64>().ok().filter(|e| *e > 0);
+            cookie.http_only = http_only;
+            jar.insert(cookie);
+        }
+        jar
+    }
+
+    /// 解析 JSON 格式的 cookie，支持本库保存的 cookies.json、
+    /// 浏览器插件导出的数组（Cookie-Editor、EditThisCookie 等）、扫码登录保存的 web_login.json，
+    /// 以及 `{"SESSDATA": "..."}` 形式的键值对
+    pub fn parse_json(text: &str) -> Result<CookieJar> {
+        let value: serde_json::Value = serde_json::from_str(text)?;
+        match value {
+            serde_json::Value::Array(items) => {
+                let mut jar = CookieJar::default();
+                for item in items {
+                    let exported: ExportedCookie = serde_json::from_value(item)?;
+                    jar.insert(exported.into_cookie());
+                }
+                Ok(jar)
+            }
+            serde_json::Value::Object(map) => {
+                if map.contains_key("cookies") {
+                    let jar: CookieJar = serde_json::from_value(serde_json::Value::Object(map))?;
+                    return Ok(jar);
+                }
+                if let Some(serde_json::Value::String(url)) = map.get("url") {
+                    return CookieJar::from_cross_domain_url(url);
+                }
+                let mut jar = CookieJar::default();
+                for (name, value) in map {
+                    let value = match value {
+                        serde_json::Value::String(value) => value,
+                        other => other.to_string(),
+                    };
+                    jar.insert(Cookie::new(name, value, DEFAULT_COOKIE_DOMAIN));
+                }
+                Ok(jar)
+            }
+            _ => Err(Error::ParamError("不支持的 cookie JSON 格式".to_string())),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cookies.is_empty()
+    }
+
+    /// 插入或替换同名、同域名、同路径的 cookie，已过期的 cookie 会被删除
+    pub fn insert(&mut self, cookie: Cookie) {
+        self.cookies.retain(|c| !c.same_key(&cookie));
+        if !cookie.is_expired(chrono::Utc::now().timestamp()) {
+            self.cookies.push(cookie);
+        }
+    }
+
+    pub fn remove(&mut self, name: &str) {
+        self.cookies.retain(|c| c.name != name);
+    }
+
+    /// 按名称查找未过期的 cookie，不区分域名
+    pub fn get(&self, name: &str) -> Option<&Cookie> {
+        let now = chrono::Utc::now().timestamp();
+        self.cookies
+            .iter()
+            .find(|c| c.name == name && !c.is_expired(now))
+    }
+
+    pub fn remove_expired(&mut self) {
+        let now = chrono::Utc::now().timestamp();
+        self.cookies.retain(|c| !c.is_expired(now));
+    }
+
+    /// 生成发送到 url 的 Cookie 请求头
+    pub fn cookie_header(&self, url: &str) -> String {
+        self.cookie_header_with(url, &[])
+    }
+
+    /// 生成发送到 url 的 Cookie 请求头，并附加 extra 中额外的 cookie（同名时覆盖）
+    pub fn cookie_header_with(&self, url: &str, extra: &[(&str, &str)]) -> String {
+        let Ok(url) = reqwest::Url::parse(url) else {
+            return String::new();
+        };
+        let host = url.host_str().unwrap_or_default().to_ascii_lowercase();
+        let https = url.scheme() == "https";
+        let now = chrono::Utc::now().timestamp();
+        let mut pairs: Vec<(&str, &str)> = self
+            .cookies
+            .iter()
+            .filter(|c| !c.is_expired(now) && c.matches(&host, url.path(), https))
+            .filter(|c| !extra.iter().any(|(name, _)| *name == c.name))
+            .map(|c| (c.name.as_str(), c.value.as_str()))
+            .collect();
+        pairs.extend(extra.iter().copied());
+        pairs
+            .iter()
+            .map(|(name, value)| format!("{}={}", name, value))
+            .collect::<Vec<_>>()
+            .join("; ")
+    }
+
+    /// 根据响应的 Set-Cookie 更新，返回是否有变化
+    pub fn update_from_response(&mut self, url: &reqwest::Url, headers: &reqwest::header::HeaderMap) -> bool {
+        let host = url.host_str().unwrap_or_default().to_ascii_lowercase();
+        let mut changed = false;
+        for value in headers.get_all(reqwest::header::SET_COOKIE) {
+            let Ok(value) = value.to_str() else {
+                continue;
+            };
+            if let Some(cookie) = Cookie::parse_set_cookie(value, &host) {
+                let before = self.cookies.clone();
+                self.insert(cookie);
+                changed |= before != self.cookies;
+            }
+        }
+        changed
+    }
+}
+
+/// 浏览器插件导出的 cookie
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ExportedCookie {
+    name: String,
+    value: String,
+    #[serde(default)]
+    domain: Option<String>,
+    #[serde(default)]
+    path: Option<String>,
+    #[serde(default)]
+    host_only: bool,
+    #[serde(default)]
+    expiration_date: Option<f64>,
+    #[serde(default)]
+    secure: bool,
+    #[serde(default)]
+    http_only: bool,
+}
+
+impl ExportedCookie {
+    fn into_cookie(self) -> Cookie {
+        let domain = self.domain.unwrap_or_else(|| DEFAULT_COOKIE_DOMAIN.to_string());
+        let mut cookie = Cookie::new(self.name, self.value, &domain);
+        cookie.host_only = self.host_only;
+        if let Some(path) = self.path.filter(|p| p.starts_with('/')) {
+            cookie.path = path;
+        }
+        cookie.expires = self.expiration_date.map(|e| e as i64);
+        cookie.secure = self.secure;
+        cookie.http_only = self.http_only;
+        cookie
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Cookie, CookieJar};
+
+    #[test]
+    fn test_import_formats() {
+        let netscape = "# Netscape HTTP Cookie File\n\
+            .bilibili.com\tTRUE\t/\tFALSE\t4102444800\tbili_jct\tjct\n\
+            #HttpOnly_.bilibili.com\tTRUE\t/\tTRUE\t0\tSESSDATA\ta%2Cb\n\
+            www.bilibili.com\tFALSE\t/\tFALSE\t0\tonly_www\t1\n";
+        let jar = CookieJar::import(netscape).unwrap();
+        let sessdata = jar.get("SESSDATA").unwrap();
+        assert_eq!(sessdata.value, "a%2Cb");
+        assert!(sessdata.http_only && sessdata.secure);
+        assert_eq!(sessdata.expires, None);
+        assert_eq!(jar.get("bili_jct").unwrap().expires, Some(4102444800));
+        assert_eq!(
+            jar.cookie_header("https://api.bilibili.com/"),
+            "bili_jct=jct; SESSDATA=a%2Cb"
+        );
+
+        let jar = CookieJar::import("Cookie: SESSDATA=s; bili_jct=j").unwrap();
+        assert_eq!(jar.get("SESSDATA").unwrap().value, "s");
+
+        let json = r#"[{"domain":".bilibili.com","expirationDate":4102444800.5,"hostOnly":false,"httpOnly":true,"name":"SESSDATA","path":"/","secure":true,"value":"s"}]"#;
+        let jar = CookieJar::import(json).unwrap();
+        assert_eq!(jar.get("SESSDATA").unwrap().expires, Some(4102444800));
+        assert_eq!(jar.get("SESSDATA").unwrap().domain, "bilibili.com");
+
+        let jar = CookieJar::import(r#"{"SESSDATA":"s","DedeUserID":1}"#).unwrap();
+        assert_eq!(jar.get("DedeUserID").unwrap().value, "1");
+
+        assert!(CookieJar::import("   ").is_err());
+    }
+
+    #[test]
+    fn test_cross_domain_url() {
+        let url = "https://passport.biligame.com/x/passport-login/web/crossDomain?DedeUserID=1&DedeUserID__ckMd5=abc&Expires=4102444800&SESSDATA=a,b&bili_jct=jct&gourl=https%3A%2F%2Fwww.bilibili.com";
+        let jar = CookieJar::from_cross_domain_url(url).unwrap();
+        assert_eq!(jar.get("SESSDATA").unwrap().value, "a%2Cb");
+        assert_eq!(jar.get("bili_jct").unwrap().expires, Some(4102444800));
+        assert!(jar.get("gourl").is_none());
+        assert!(jar.get("Expires").is_none());
+    }
+
+    #[test]
+    fn test_cookie_header_domain_match() {
+        let mut jar = CookieJar::parse_header("SESSDATA=s; bili_jct=j", "bilibili.com");
+        jar.insert(Cookie::new("other", "o", "bilibili.tv"));
+        let header = jar.cookie_header("https://api.bilibili.com/x/web-interface/nav");
+        assert_eq!(header, "SESSDATA=s; bili_jct=j");
+        let header = jar.cookie_header_with(
+            "https://api.bilibili.com/pgc/view/web/season?ep_id=1",
+            &[("CURRENT_FNVAL", "4048")],
+        );
+        assert_eq!(header, "SESSDATA=s; bili_jct=j; CURRENT_FNVAL=4048");
+        assert_eq!(jar.cookie_header("https://www.bilibili.tv/"), "other=o");
+    }
+
+    #[test]
+    fn test_set_cookie_update() {
+        let mut jar = CookieJar::parse_header("SESSDATA=old", "bilibili.com");
+        let url = reqwest::Url::parse("https://passport.bilibili.com/x/passport-login/web/cookie/refresh").unwrap();
+        let mut headers = reqwest::header::HeaderMap::new();
+        headers.append(
+            reqwest::header::SET_COOKIE,
+            "SESSDATA=new; Path=/; Domain=bilibili.com; Expires=Fri, 01 Jan 2100 00:00:00 GMT; HttpOnly; Secure"
+                .parse()
+                .unwrap(),
+        );
+        headers.append(
+            reqwest::header::SET_COOKIE,
+            "host_only=1; Max-Age=60".parse().unwrap(),
+        );
+        assert!(jar.update_from_response(&url, &headers));
+        let sessdata = jar.get("SESSDATA").unwrap();
+        assert_eq!(sessdata.value, "new");
+        assert_eq!(sessdata.expires, Some(4102444800));
+        assert!(sessdata.http_only && sessdata.secure);
+        assert_eq!(jar.cookies.len(), 2);
+        assert_eq!(
+            jar.cookie_header("https://api.bilibili.com/"),
+            "SESSDATA=new"
+        );
+        // 其他站点不能设置 bilibili.com 的 cookie
+        let tv_url = reqwest::Url::parse("https://api.bilibili.tv/intl/gateway/web/playurl").unwrap();
+        headers.clear();
+        headers.append(
+            reqwest::header::SET_COOKIE,
+            "SESSDATA=evil; Domain=bilibili.com; Path=/"
+                .parse()
+                .unwrap(),
+        );
+        assert!(!jar.update_from_response(&tv_url, &headers));
+        assert_eq!(jar.get("SESSDATA").unwrap().value, "new");
+        headers.clear();
+        headers.append(
+            reqwest::header::SET_COOKIE,
+            "SESSDATA=; Domain=bilibili.com; Path=/; Max-Age=0".parse().unwrap(),
+        );
+        assert!(jar.update_from_response(&url, &headers));
+        assert!(jar.get("SESSDATA").is_none());
+    }
+}
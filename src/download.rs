@@ -5,13 +5,14 @@ use crate::{BBDD, Result};
 impl BBDD {
 
     pub async fn download_resource_head(&self, url: &str) -> Result<reqwest::Response> {
+        let cookie = self.cookie.read().unwrap().cookie_header(url);
         let response = self
-            .agent
+            .media_agent
             .request(Method::HEAD, url)
             .header("Referer", "https://www.bilibili.com")
             .header("User-Agent", &self.ua)
             .header("Accept-Encoding", "gzip, deflate")
-            .header("Cookie", &self.cookie)
+            .header("Cookie", cookie)
             .send()
             .await?;
         Ok(response)
@@ -28,13 +29,14 @@ impl BBDD {
     ) -> Result<reqwest::Response> {
         let range_start = range_start.into();
         let range_end = range_end.into();
+        let cookie = self.cookie.read().unwrap().cookie_header(url);
         let mut request_builder = self
-            .agent
+            .media_agent
             .request(Method::GET, url)
             .header("Referer", "https://www.bilibili.com")
             .header("User-Agent", &self.ua)
             .header("Accept-Encoding", "gzip, deflate")
-            .header("Cookie", &self.cookie);
+            .header("Cookie", cookie);
         if range_start.is_some() || range_end.is_some() {
             let range_header = match (range_start, range_end) {
                 (Some(start), Some(end)) => format!("bytes={}-{}", start, end),
@@ -22,3 +22,108 @@ pub type BBDDResult<T> = std::result::Result<T, BBDDError>;
 
 pub type Error = BBDDError;
 pub type Result<T> = BBDDResult<T>;
+
+/// 接口错误码的分类
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApiErrorKind {
+    /// -101 账号未登录，-2 access_key 错误
+    LoginRequired,
+    /// -102 账号被封停
+    AccountBanned,
+    /// -111 csrf 校验失败
+    CsrfFailed,
+    /// -403 访问权限不足，87007/87008 充电专属
+    PermissionDenied,
+    /// -10403 大会员专享
+    VipRequired,
+    /// -10403 / 6002003 地区限制
+    RegionBlocked,
+    /// -412 请求被拦截，-352 风控校验失败，-509 请求过于频繁
+    RateLimited,
+    /// -404 不存在
+    NotFound,
+    /// 62002 稿件不可见，62004 审核中，62012 仅UP主自己可见
+    Invisible,
+    Other,
+}
+
+impl ApiErrorKind {
+    /// -10403 同时用于大会员和地区限制，需要根据 message 区分
+    pub fn from_code(code: i32, message: &str) -> ApiErrorKind {
+        match code {
+            -101 | -2 => ApiErrorKind::LoginRequired,
+            -102 => ApiErrorKind::AccountBanned,
+            -111 => ApiErrorKind::CsrfFailed,
+            -403 | 87007 | 87008 => ApiErrorKind::PermissionDenied,
+            -10403 if message.contains("地区") => ApiErrorKind::RegionBlocked,
+            -10403 => ApiErrorKind::VipRequired,
+            6002003 => ApiErrorKind::RegionBlocked,
+            -412 | -352 | -509 => ApiErrorKind::RateLimited,
+            -404 => ApiErrorKind::NotFound,
+            62002 | 62004 | 62012 => ApiErrorKind::Invisible,
+            _ => ApiErrorKind::Other,
+        }
+    }
+}
+
+impl BBDDError {
+    /// 非 ApiError 时返回 None
+    pub fn api_error_kind(&self) -> Option<ApiErrorKind> {
+        match self {
+            BBDDError::ApiError { code, message } => Some(ApiErrorKind::from_code(*code, message)),
+            _ => None,
+        }
+    }
+
+    pub fn is_login_required(&self) -> bool {
+        self.api_error_kind() == Some(ApiErrorKind::LoginRequired)
+    }
+
+    pub fn is_region_blocked(&self) -> bool {
+        self.api_error_kind() == Some(ApiErrorKind::RegionBlocked)
+    }
+
+    pub fn is_rate_limited(&self) -> bool {
+        self.api_error_kind() == Some(ApiErrorKind::RateLimited)
+    }
+
+    pub fn is_not_found(&self) -> bool {
+        self.api_error_kind() == Some(ApiErrorKind::NotFound)
+    }
+
+    pub fn is_permission_denied(&self) -> bool {
+        matches!(
+            self.api_error_kind(),
+            Some(ApiErrorKind::PermissionDenied | ApiErrorKind::VipRequired)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ApiErrorKind, BBDDError};
+
+    #[test]
+    fn test_api_error_kind() {
+        let error = |code: i32, message: &str| BBDDError::ApiError {
+            code,
+            message: message.to_string(),
+        };
+        assert!(error(-101, "账号未登录").is_login_required());
+        assert!(error(-412, "请求被拦截").is_rate_limited());
+        assert!(error(-352, "").is_rate_limited());
+        assert!(error(-10403, "抱歉您所在地区不可观看！").is_region_blocked());
+        assert_eq!(
+            error(-10403, "大会员专享限制").api_error_kind(),
+            Some(ApiErrorKind::VipRequired)
+        );
+        assert!(error(87007, "").is_permission_denied());
+        assert!(error(-404, "啥都木有").is_not_found());
+        assert_eq!(
+            error(62002, "稿件不可见").api_error_kind(),
+            Some(ApiErrorKind::Invisible)
+        );
+        assert_eq!(error(1, "").api_error_kind(), Some(ApiErrorKind::Other));
+        assert_eq!(BBDDError::StateError(String::new()).api_error_kind(), None);
+    }
+}
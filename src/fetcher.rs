@@ -1,4 +1,5 @@
 use crate::{
+    area::Area,
     error::{Error, Result},
     util::take_json_field,
 };
@@ -8,8 +9,6 @@ use std::sync::OnceLock;
 
 use crate::BBDD;
 
-static WBI_MIXIN_KEY: OnceLock<String> = OnceLock::new();
-
 impl BBDD {
     pub async fn fetch_video_info(&self, aid: i64) -> Result<VideoInfo> {
         let url = format!("https://api.bilibili.com/x/web-interface/view?aid={}", aid);
@@ -111,18 +110,34 @@ impl BBDD {
     }
 
     pub async fn play_url_with_qn(&self, aid: i64, cid: i64, qn: i64) -> Result<VideoPlayUrl> {
-        let prefix = "https://api.bilibili.com/x/player/wbi/playurl?";
-        let mut api = format!("support_multi_audio=true&from_client=BROWSER&avid={aid}&cid={cid}&fnval=4048&fnver=0&fourk=1&otype=json&qn={qn}");
-        if self.cookie.is_empty() {
-            api.push_str("&try_look=1");
+        let mut params: Vec<(String, String)> = [
+            ("support_multi_audio", "true".to_string()),
+            ("from_client", "BROWSER".to_string()),
+            ("avid", aid.to_string()),
+            ("cid", cid.to_string()),
+            ("fnval", "4048".to_string()),
+            ("fnver", "0".to_string()),
+            ("fourk", "1".to_string()),
+            ("otype", "json".to_string()),
+            ("qn", qn.to_string()),
+        ]
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), v))
+        .collect();
+        if !self.has_cookie("SESSDATA") {
+            params.push(("try_look".to_string(), "1".to_string()));
         }
-        api.push_str(format!("&wts={}", chrono::Utc::now().timestamp()).as_str());
-        let url = format!("{}{}", prefix, wbi_sign(self, api.as_str()).await?);
-        match self.get_data::<serde_json::Value>(url.as_str(), None).await {
+        match self
+            .get_wbi_data::<serde_json::Value>(
+                "https://api.bilibili.com/x/player/wbi/playurl",
+                &params,
+            )
+            .await
+        {
             Ok(json) => match parse_play_url(json.clone()) {
-                Ok(play_url) if !play_url.dash.video.is_empty() => Ok(play_url),
+                Ok(play_url) if play_url.has_streams() => Ok(play_url),
                 _ => {
-                    tracing::warn!("playurl 解析失败或 dash 为空，尝试网页源码兜底");
+                    tracing::warn!("playurl 解析失败或没有可用的视频流，尝试网页源码兜底");
                     play_url_from_html(self, aid, cid, None).await
                 }
             },
@@ -138,6 +153,7 @@ impl BBDD {
         }
     }
 
+    /// 地区限制的番剧会通过 `BBDDBuilder::area_host` 配置的解析服务器重试
     pub async fn play_url_ep(
         &self,
         aid: i64,
@@ -145,17 +161,13 @@ impl BBDD {
         ep_id: i64,
         qn: i64,
     ) -> Result<VideoPlayUrl> {
-        let prefix = "https://api.bilibili.com/pgc/player/web/v2/playurl?";
-        let mut api = format!(
-            "avid={aid}&cid={cid}&fnval=4048&fnver=0&fourk=1&otype=json&qn={qn}&module=bangumi&ep_id={ep_id}&session="
-        );
-        if self.cookie.is_empty() {
-            api.push_str("&try_look=1");
-        }
-        api.push_str(format!("&wts={}", chrono::Utc::now().timestamp()).as_str());
-        let url = format!("{}{}", prefix, api);
-        let result: serde_json::Value = match self.get_result(url.as_str(), None).await {
+        let result: serde_json::Value = match self
+            .with_area_fallback(|area| self.play_url_ep_result(aid, cid, ep_id, qn, area))
+            .await
+        {
             Ok(v) => v,
+            // 网页源码同样受地区限制
+            Err(e) if e.is_region_blocked() => return Err(e),
             Err(Error::ApiError { code, message }) => {
                 tracing::warn!(
                     "pgc playurl 接口返回错误，尝试网页源码兜底: code={}, message={}",
@@ -166,29 +178,75 @@ impl BBDD {
             }
             Err(e) => return Err(e),
         };
+        // v2 接口的试看标记在 video_info 之外
+        let outer_preview = preview_flag(&result);
         let result = if let Some(video_info) = result.get("video_info") {
             video_info.clone()
         } else {
             result
         };
         match parse_play_url(result.clone()) {
-            Ok(play_url) if !play_url.dash.video.is_empty() => Ok(play_url),
+            Ok(mut play_url) if play_url.has_streams() => {
+                play_url.is_preview |= outer_preview;
+                Ok(play_url)
+            }
             _ => {
-                tracing::warn!("pgc playurl 解析失败或 dash 为空，尝试网页源码兜底");
+                tracing::warn!("pgc playurl 解析失败或没有可用的视频流，尝试网页源码兜底");
                 play_url_from_html(self, aid, cid, Some(ep_id)).await
             }
         }
     }
+
+    /// area 为空时请求主站，否则请求解析服务器，解析服务器使用 access_key 识别账号
+    async fn play_url_ep_result(
+        &self,
+        aid: i64,
+        cid: i64,
+        ep_id: i64,
+        qn: i64,
+        area: Option<(Area, String)>,
+    ) -> Result<serde_json::Value> {
+        let prefix = match &area {
+            None => "https://api.bilibili.com/pgc/player/web/v2/playurl?".to_string(),
+            Some((_, host)) => format!("{}/pgc/player/web/playurl?", host),
+        };
+        let mut api = format!(
+            "avid={aid}&cid={cid}&fnval=4048&fnver=0&fourk=1&otype=json&qn={qn}&module=bangumi&ep_id={ep_id}&session="
+        );
+        if !self.has_cookie("SESSDATA") {
+            api.push_str("&try_look=1");
+        }
+        if let Some((area, _)) = &area {
+            api.push_str(format!("&area={}", area.as_str()).as_str());
+            if let Some(access_key) = self.access_key() {
+                api.push_str(format!("&access_key={}", access_key).as_str());
+            }
+        }
+        api.push_str(format!("&wts={}", chrono::Utc::now().timestamp()).as_str());
+        let url = format!("{}{}", prefix, api);
+        self.get_result(url.as_str(), None).await
+    }
 }
 
 fn parse_play_url(mut json: serde_json::Value) -> Result<VideoPlayUrl> {
     if let Some(dash) = json.get_mut("dash") {
         normalize_audio_list(dash);
     }
-    let play_url: VideoPlayUrl = serde_json::from_value(json)?;
+    normalize_durl(&mut json);
+    let is_preview = preview_flag(&json);
+    let mut play_url: VideoPlayUrl = serde_json::from_value(json)?;
+    play_url.is_preview = is_preview;
     Ok(play_url)
 }
 
+/// 未登录或没有大会员时，番剧的 playurl 只返回几分钟的试看片段
+fn preview_flag(json: &serde_json::Value) -> bool {
+    let is_preview = &json["is_preview"];
+    is_preview.as_bool() == Some(true)
+        || is_preview.as_i64().is_some_and(|v| v != 0)
+        || json["play_check"]["play_detail"].as_str() == Some("PLAY_PREVIEW")
+}
+
 fn normalize_audio_list(dash: &mut serde_json::Value) {
     let dolby_audio = dash
         .get("dolby")
@@ -216,57 +274,21 @@ fn normalize_audio_list(dash: &mut serde_json::Value) {
     }
 }
 
-async fn wbi_sign(client: &BBDD, api: &str) -> Result<String> {
-    let key = match wbi_mixin_key(client).await {
-        Ok(key) => key,
-        Err(_) => String::new(),
-    };
-    let md5 = md5::compute(format!("{}{}", api, key).as_bytes());
-    let sign = hex::encode(md5.0);
-    Ok(format!("{api}&w_rid={sign}"))
-}
-
-async fn wbi_mixin_key(client: &BBDD) -> Result<String> {
-    if let Some(key) = WBI_MIXIN_KEY.get() {
-        return Ok(key.clone());
+/// durl 分段没有备用地址时 backup_url 为 null，dash 为 null 时视为没有 dash
+fn normalize_durl(json: &mut serde_json::Value) {
+    if let Some(object) = json.as_object_mut() {
+        object.retain(|key, value| !(value.is_null() && (key == "dash" || key == "durl")));
     }
-    let nav = client.web_nav().await?;
-    let orig = format!(
-        "{}{}",
-        r_sub_string(nav.wbi_img.img_url.as_str())?,
-        r_sub_string(nav.wbi_img.sub_url.as_str())?
-    );
-    let key = mixin_key(orig.as_str())?;
-    let _ = WBI_MIXIN_KEY.set(key.clone());
-    Ok(key)
-}
-
-fn r_sub_string(url: &str) -> Result<&str> {
-    let last = url
-        .rsplit('/')
-        .next()
-        .ok_or_else(|| Error::StateError("Invalid wbi url".to_string()))?;
-    if let Some((stem, _ext)) = last.rsplit_once('.') {
-        Ok(stem)
-    } else {
-        Err(Error::StateError("Invalid wbi url".to_string()))
-    }
-}
-
-fn mixin_key(orig: &str) -> Result<String> {
-    const MIXIN_KEY_ENC_TAB: [usize; 32] = [
-        46, 47, 18, 2, 53, 8, 23, 32, 15, 50, 10, 31, 58, 3, 45, 35, 27, 43, 5, 49, 33, 9,
-        42, 19, 29, 28, 14, 39, 12, 38, 41, 13,
-    ];
-    let chars: Vec<char> = orig.chars().collect();
-    let mut out = String::with_capacity(32);
-    for idx in MIXIN_KEY_ENC_TAB {
-        let ch = *chars
-            .get(idx)
-            .ok_or_else(|| Error::StateError("Invalid wbi key material".to_string()))?;
-        out.push(ch);
+    let Some(durl) = json.get_mut("durl").and_then(|v| v.as_array_mut()) else {
+        return;
+    };
+    for segment in durl {
+        if segment.get("backup_url").is_some_and(|v| v.is_null())
+            && let Some(segment) = segment.as_object_mut()
+        {
+            segment.remove("backup_url");
+        }
     }
-    Ok(out)
 }
 
 async fn play_url_from_html(
@@ -288,9 +310,9 @@ async fn play_url_from_html(
         .cloned()
         .ok_or_else(|| Error::StateError("playinfo 缺少 data 字段".to_string()))?;
     let play_url = parse_play_url(data)?;
-    if play_url.dash.video.is_empty() {
+    if !play_url.has_streams() {
         Err(Error::StateError(
-            "playinfo 解析失败: dash.video 为空".to_string(),
+            "playinfo 解析失败: dash.video 和 durl 均为空".to_string(),
         ))
     } else {
         Ok(play_url)
@@ -374,7 +396,64 @@ pub struct VideoPlayUrl {
     pub video_codecid: i64,
     pub seek_param: String,
     pub seek_type: String,
+    /// 没有 dash 时为空，此时使用 durl
+    #[serde(default)]
     pub dash: VideoDash,
+    /// 旧的 FLV / MP4 分段地址，按 order 顺序拼接为完整视频
+    #[serde(default)]
+    pub durl: Vec<VideoDurl>,
+    /// 接口标记为试看，由 `parse_play_url` 根据 is_preview 等字段设置
+    #[serde(default, skip_deserializing)]
+    pub is_preview: bool,
+}
+
+/// 播放地址是完整视频还是试看片段
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PreviewStatus {
+    Full,
+    /// 时长单位为毫秒，完整时长未知时为 0
+    Preview { preview_ms: i64, duration_ms: i64 },
+}
+
+impl VideoPlayUrl {
+    /// dash 或 durl 中至少有一个可用的视频流
+    pub fn has_streams(&self) -> bool {
+        !self.dash.video.is_empty() || !self.durl.is_empty()
+    }
+
+    /// 没有 dash 视频流时使用 durl 分段下载
+    pub fn is_durl(&self) -> bool {
+        self.dash.video.is_empty() && !self.durl.is_empty()
+    }
+
+    /// durl 分段的扩展名，format 为 flv、flv720、mp4、hdmp4 等
+    pub fn durl_extension(&self) -> &str {
+        if self.format.contains("mp4") {
+            "mp4"
+        } else {
+            "flv"
+        }
+    }
+
+    /// duration_ms 为视频的完整时长（毫秒），例如 `EpisodeInfo.duration`，
+    /// 接口没有标记试看但返回的时长明显短于完整时长时同样视为试看
+    pub fn preview_status(&self, duration_ms: Option<i64>) -> PreviewStatus {
+        let duration_ms = duration_ms.unwrap_or_default();
+        let preview_ms = if self.timelength > 0 {
+            self.timelength
+        } else {
+            self.dash.duration * 1000
+        };
+        let truncated = duration_ms > 0 && preview_ms > 0 && preview_ms * 10 < duration_ms * 9;
+        if self.is_preview || truncated {
+            PreviewStatus::Preview {
+                preview_ms,
+                duration_ms,
+            }
+        } else {
+            PreviewStatus::Full
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
@@ -385,6 +464,28 @@ pub struct VideoDash {
     pub audio: Vec<VideoMedia>,
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct VideoDurl {
+    /// 分段序号，从 1 开始
+    pub order: i64,
+    /// 分段时长（毫秒）
+    pub length: i64,
+    /// 分段大小（字节）
+    pub size: i64,
+    pub url: String,
+    #[serde(default)]
+    pub backup_url: Vec<String>,
+}
+
+impl VideoDurl {
+    /// 第一个为主地址，其余为备用地址
+    pub fn urls(&self) -> Vec<String> {
+        let mut urls = vec![self.url.clone()];
+        urls.extend(self.backup_url.clone());
+        urls
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
 pub struct VideoMedia {
     pub id: i64,
@@ -401,6 +502,45 @@ pub struct VideoMedia {
     pub codecid: i64,
 }
 
+/// 视频流的动态范围
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DynamicRange {
+    #[default]
+    Sdr,
+    /// HDR10 / HLG
+    Hdr,
+    DolbyVision,
+}
+
+impl VideoMedia {
+    /// 根据 codecs 判断动态范围：dvh1、dvhe、dav1 等为杜比视界，
+    /// AV1 按 codecs 中的传输特性（16 为 PQ，18 为 HLG）判断，
+    /// HEVC Main 10 或清晰度为 125 时为 HDR
+    pub fn dynamic_range(&self) -> DynamicRange {
+        let codecs = self.codecs.to_ascii_lowercase();
+        let mut fields = codecs.split('.');
+        let fourcc = fields.next().unwrap_or_default();
+        if fourcc.starts_with("dv") || fourcc == "dav1" || self.id == 126 {
+            return DynamicRange::DolbyVision;
+        }
+        let hdr = match fourcc {
+            "av01" => matches!(fields.nth(6), Some("16" | "18")),
+            "hev1" | "hvc1" => fields.next() == Some("2"),
+            _ => false,
+        };
+        if hdr || self.id == 125 {
+            DynamicRange::Hdr
+        } else {
+            DynamicRange::Sdr
+        }
+    }
+
+    /// codecs 中的 sample entry，例如 avc1、hev1、dvh1
+    pub fn codec_tag(&self) -> &str {
+        self.codecs.split('.').next().unwrap_or_default()
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
 pub struct EpInfo {
     pub actors: String,
@@ -454,25 +594,10 @@ pub struct SeasonInfo {
 
 #[cfg(test)]
 mod tests {
-    use super::{mixin_key, r_sub_string};
     use crate::parse::VideoType;
 
     const BV_ID: &'static str = "BV1P4411T73c";
 
-    #[test]
-    fn test_r_sub_string_uses_last_dot() {
-        let url = "https://i0.hdslb.com/bfs/wbi/abc.def.png";
-        let stem = r_sub_string(url).unwrap();
-        assert_eq!(stem, "abc.def");
-    }
-
-    #[test]
-    fn test_mixin_key_length() {
-        let orig = "0123456789abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ__";
-        let key = mixin_key(orig).unwrap();
-        assert_eq!(key.len(), 32);
-    }
-
     #[tokio::test]
     async fn test_fetch_video_info() {
         crate::tests::log_init();
@@ -496,6 +621,88 @@ mod tests {
         println!("{:#?}", play_url);
     }
 
+    #[test]
+    fn test_preview_status() {
+        use crate::fetcher::{PreviewStatus, VideoPlayUrl};
+        let mut play_url = VideoPlayUrl {
+            timelength: 360_000,
+            ..Default::default()
+        };
+        assert_eq!(play_url.preview_status(None), PreviewStatus::Full);
+        assert_eq!(play_url.preview_status(Some(365_000)), PreviewStatus::Full);
+        assert_eq!(
+            play_url.preview_status(Some(1_420_000)),
+            PreviewStatus::Preview {
+                preview_ms: 360_000,
+                duration_ms: 1_420_000
+            }
+        );
+        assert!(!super::preview_flag(&serde_json::json!({ "is_preview": 0 })));
+        assert!(super::preview_flag(&serde_json::json!({ "is_preview": 1 })));
+        assert!(super::preview_flag(
+            &serde_json::json!({ "play_check": { "play_detail": "PLAY_PREVIEW" } })
+        ));
+        play_url.is_preview = true;
+        assert!(matches!(
+            play_url.preview_status(None),
+            PreviewStatus::Preview { duration_ms: 0, .. }
+        ));
+    }
+
+    #[test]
+    fn test_parse_durl_play_url() {
+        let play_url = super::parse_play_url(serde_json::json!({
+            "from": "local", "result": "suee", "message": "", "quality": 64, "format": "flv720",
+            "timelength": 1_250_000, "accept_format": "hdflv2,flv,flv720,flv480,mp4",
+            "accept_description": ["高清 1080P", "高清 720P"], "accept_quality": [80, 64],
+            "video_codecid": 7, "seek_param": "start", "seek_type": "offset", "dash": null,
+            "durl": [
+                {"order": 1, "length": 360_000, "size": 31_000_000, "ahead": "", "vhead": "",
+                 "url": "https://upos-sz-mirrorcos.bilivideo.com/a-1-64.flv", "backup_url": null},
+                {"order": 2, "length": 890_000, "size": 76_000_000, "ahead": "", "vhead": "",
+                 "url": "https://upos-sz-mirrorcos.bilivideo.com/a-2-64.flv",
+                 "backup_url": ["https://upos-sz-mirrorhw.bilivideo.com/a-2-64.flv"]}
+            ]
+        }))
+        .unwrap();
+        assert!(play_url.has_streams());
+        assert!(play_url.is_durl());
+        assert_eq!(play_url.durl_extension(), "flv");
+        assert_eq!(play_url.durl.len(), 2);
+        assert!(play_url.durl[0].backup_url.is_empty());
+        assert_eq!(play_url.durl[1].urls().len(), 2);
+    }
+
+    #[test]
+    fn test_dynamic_range() {
+        use crate::fetcher::{DynamicRange, VideoMedia};
+        let media = |id: i64, codecs: &str| VideoMedia {
+            id,
+            codecs: codecs.to_string(),
+            ..Default::default()
+        };
+        assert_eq!(media(80, "avc1.640032").dynamic_range(), DynamicRange::Sdr);
+        assert_eq!(
+            media(80, "hev1.1.6.L150.90").dynamic_range(),
+            DynamicRange::Sdr
+        );
+        assert_eq!(
+            media(125, "hev1.2.4.L156.90").dynamic_range(),
+            DynamicRange::Hdr
+        );
+        assert_eq!(
+            media(120, "av01.0.13M.10.0.110.09.16.09.0").dynamic_range(),
+            DynamicRange::Hdr
+        );
+        assert_eq!(
+            media(120, "av01.0.13M.10.0.110.01.01.01.0").dynamic_range(),
+            DynamicRange::Sdr
+        );
+        let dolby = media(126, "dvh1.08.07");
+        assert_eq!(dolby.dynamic_range(), DynamicRange::DolbyVision);
+        assert_eq!(dolby.codec_tag(), "dvh1");
+    }
+
     #[tokio::test]
     async fn test_fetch_ep_info() {
         crate::tests::log_init();
@@ -0,0 +1,209 @@
+use crate::cookie::{Cookie, DEFAULT_COOKIE_DOMAIN};
+use crate::{BBDD, Error, Result};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+const SPI_URL: &str = "https://api.bilibili.com/x/frontend/finger/spi";
+
+const TICKET_URL: &str =
+    "https://api.bilibili.com/bapis/bilibili.api.ticket.v1.Ticket/GenWebTicket";
+
+const TICKET_HMAC_KEY: &[u8] = b"XgwSnGZ1p";
+
+/// 浏览器指纹相关的 cookie
+pub const FINGERPRINT_COOKIES: [&str; 5] = ["buvid3", "buvid4", "b_nut", "_uuid", "bili_ticket"];
+
+/// 获取失败后再次尝试的间隔，避免每个请求都重复获取
+const RETRY_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+/// buvid3 等 cookie 的有效期
+const FINGERPRINT_TTL: i64 = 365 * 24 * 60 * 60;
+
+impl BBDD {
+    /// 缺少或已过期的指纹 cookie
+    pub fn missing_fingerprint(&self) -> Vec<&'static str> {
+        let now = chrono::Utc::now().timestamp();
+        let jar = self.cookie.read().unwrap();
+        FINGERPRINT_COOKIES
+            .into_iter()
+            .filter(|name| jar.get(name).is_none_or(|c| c.is_expired(now)))
+            .collect()
+    }
+
+    /// 获取缺少的 buvid3、buvid4、b_nut、_uuid 和 bili_ticket，并写入 cookie
+    pub async fn ensure_fingerprint(&self) -> Result<()> {
+        let missing = self.missing_fingerprint();
+        if missing.is_empty() {
+            return Ok(());
+        }
+        let now = chrono::Utc::now().timestamp();
+        let expires = Some(now + FINGERPRINT_TTL);
+        let mut cookies = vec![];
+        if missing.contains(&"buvid3") || missing.contains(&"buvid4") {
+            let spi: SpiData = self.get_data(SPI_URL, None).await?;
+            cookies.push(("buvid3", spi.b_3, expires));
+            cookies.push(("buvid4", spi.b_4, expires));
+        }
+        if missing.contains(&"b_nut") {
+            cookies.push(("b_nut", now.to_string(), expires));
+        }
+        if missing.contains(&"_uuid") {
+            cookies.push(("_uuid", gen_uuid(now * 1000), expires));
+        }
+        if missing.contains(&"bili_ticket") {
+            let ticket = self.gen_web_ticket(now).await?;
+            let ticket_expires = ticket.created_at + ticket.ttl;
+            cookies.push(("bili_ticket", ticket.ticket, Some(ticket_expires)));
+            cookies.push((
+                "bili_ticket_expires",
+                ticket_expires.to_string(),
+                Some(ticket_expires),
+            ));
+        }
+        {
+            let mut jar = self.cookie.write().unwrap();
+            for (name, value, expires) in cookies {
+                let mut cookie = Cookie::new(name, value, DEFAULT_COOKIE_DOMAIN);
+                cookie.expires = expires;
+                jar.insert(cookie);
+            }
+        }
+        self.save_cookies()
+    }
+
+    async fn gen_web_ticket(&self, ts: i64) -> Result<TicketData> {
+        let csrf = self.csrf();
+        let query = serde_json::json!({
+            "key_id": "ec02",
+            "hexsign": ticket_hexsign(ts)?,
+            "context[ts]": ts,
+            "csrf": csrf,
+        });
+        self.take_data(self.request(reqwest::Method::POST, TICKET_URL, Some(query), None))
+            .await
+    }
+
+    /// 向 api.bilibili.com 发送请求前调用，缺少指纹 cookie 时获取，返回是否获取成功。
+    /// 获取失败后一段时间内不再尝试
+    pub(crate) async fn fingerprint_before_send(&self, host: &str) -> bool {
+        if !self.fingerprint || host != "api.bilibili.com" {
+            return false;
+        }
+        {
+            let mut last_attempt = self.fingerprint_attempt.lock().unwrap();
+            if last_attempt.is_some_and(|t| t.elapsed() < RETRY_INTERVAL)
+                || self.missing_fingerprint().is_empty()
+            {
+                return false;
+            }
+            // 先记录时间，获取指纹时发出的请求不会再次进入这里
+            *last_attempt = Some(Instant::now());
+        }
+        match Box::pin(self.ensure_fingerprint()).await {
+            Ok(()) => {
+                *self.fingerprint_attempt.lock().unwrap() = None;
+                true
+            }
+            Err(e) => {
+                tracing::warn!("获取指纹 cookie 失败: {}", e);
+                false
+            }
+        }
+    }
+
+    /// 请求构建时还没有指纹 cookie，补充到 Cookie 请求头中
+    pub(crate) fn append_fingerprint_cookies(&self, request: &mut reqwest::Request) {
+        let header = request
+            .headers()
+            .get(reqwest::header::COOKIE)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or_default()
+            .to_string();
+        let present: Vec<&str> = header
+            .split(';')
+            .filter_map(|pair| pair.split_once('=').map(|(name, _)| name.trim()))
+            .collect();
+        let now = chrono::Utc::now().timestamp();
+        let jar = self.cookie.read().unwrap();
+        let mut pairs: Vec<String> = header
+            .split(';')
+            .map(str::trim)
+            .filter(|pair| !pair.is_empty())
+            .map(str::to_string)
+            .collect();
+        for name in FINGERPRINT_COOKIES {
+            if let Some(cookie) = jar.get(name)
+                && !cookie.is_expired(now)
+                && !present.contains(&name)
+            {
+                pairs.push(format!("{}={}", cookie.name, cookie.value));
+            }
+        }
+        if let Ok(value) = reqwest::header::HeaderValue::from_str(pairs.join("; ").as_str()) {
+            request.headers_mut().insert(reqwest::header::COOKIE, value);
+        }
+    }
+}
+
+/// bili_ticket 接口的签名，HMAC-SHA256(key, "ts{ts}")
+pub fn ticket_hexsign(ts: i64) -> Result<String> {
+    let mut mac = Hmac::<sha2::Sha256>::new_from_slice(TICKET_HMAC_KEY)
+        .map_err(|e| Error::StateError(format!("Invalid hmac key: {}", e)))?;
+    mac.update(format!("ts{}", ts).as_bytes());
+    Ok(hex::encode(mac.finalize().into_bytes()))
+}
+
+/// 与网页端相同格式的 _uuid，例如 `1A2B3C4D-...-...-...-...12345infoc`
+pub fn gen_uuid(now_millis: i64) -> String {
+    const DIGITS: &[u8] = b"0123456789ABCDEF";
+    let part = |len: usize| {
+        (0..len)
+            .map(|_| DIGITS[rand::random::<u8>() as usize % 16] as char)
+            .collect::<String>()
+    };
+    format!(
+        "{}-{}-{}-{}-{}{:05}infoc",
+        part(8),
+        part(4),
+        part(4),
+        part(4),
+        part(12),
+        now_millis % 100000
+    )
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+struct SpiData {
+    b_3: String,
+    b_4: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+struct TicketData {
+    ticket: String,
+    created_at: i64,
+    /// 有效期（秒）
+    ttl: i64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{gen_uuid, ticket_hexsign};
+
+    #[test]
+    fn test_ticket_hexsign() {
+        assert_eq!(
+            ticket_hexsign(1734000000).unwrap(),
+            "3e549ef86088bcd83a89014626692bd23c2fb0c637a0d740187f9862f7a561a1"
+        );
+    }
+
+    #[test]
+    fn test_gen_uuid() {
+        let uuid = gen_uuid(1734000012345);
+        assert_eq!(uuid.len(), 8 + 4 + 4 + 4 + 12 + 4 + 5 + 5);
+        assert!(uuid.ends_with("12345infoc"));
+        assert_eq!(uuid.matches('-').count(), 4);
+    }
+}
@@ -0,0 +1,474 @@
+use crate::fetcher::{VideoDash, VideoMedia, VideoPlayUrl};
+use crate::{BBDD, Error, Result};
+use serde::{Deserialize, Deserializer, Serialize};
+
+const GATEWAY_URL: &str = "https://api.bilibili.tv/intl/gateway";
+
+/// bilibili.tv 接口默认的语言，决定标题、简介和字幕列表的语言
+pub const INTL_DEFAULT_LOCALE: &str = "zh_SG";
+
+impl BBDD {
+    /// 请求 bilibili.tv 的接口，locale 为 `s_locale` 参数，例如 zh_SG、en_US、th_TH
+    async fn get_intl_data<T: for<'de> Deserialize<'de>>(
+        &self,
+        path: &str,
+        locale: &str,
+        mut query: serde_json::Value,
+    ) -> Result<T> {
+        query["s_locale"] = serde_json::json!(locale);
+        query["platform"] = serde_json::json!("web");
+        let url = format!("{}{}", GATEWAY_URL, path);
+        self.get_data(url.as_str(), Some(query)).await
+    }
+
+    /// bilibili.tv 番剧信息
+    pub async fn fetch_intl_season(&self, season_id: i64, locale: &str) -> Result<IntlSeason> {
+        let data: serde_json::Value = self
+            .get_intl_data(
+                "/web/v2/ogv/play/season_info",
+                locale,
+                serde_json::json!({ "season_id": season_id }),
+            )
+            .await?;
+        Ok(serde_json::from_value(data["season"].clone())?)
+    }
+
+    /// bilibili.tv 番剧的全部剧集，按分区的顺序排列
+    pub async fn fetch_intl_episodes(
+        &self,
+        season_id: i64,
+        locale: &str,
+    ) -> Result<Vec<IntlEpisode>> {
+        let data: IntlSections = self
+            .get_intl_data(
+                "/web/v2/ogv/play/episodes",
+                locale,
+                serde_json::json!({ "season_id": season_id }),
+            )
+            .await?;
+        Ok(data.sections.into_iter().flat_map(|s| s.episodes).collect())
+    }
+
+    /// bilibili.tv 剧集的播放地址，转换为与主站相同的 dash 结构，需要会员的清晰度不包含在内
+    pub async fn intl_play_url(&self, ep_id: i64, qn: i64, locale: &str) -> Result<VideoPlayUrl> {
+        let data: serde_json::Value = self
+            .get_intl_data(
+                "/web/playurl",
+                locale,
+                serde_json::json!({
+                    "ep_id": ep_id,
+                    "qn": qn,
+                    "type": 0,
+                    "tf": 0,
+                    "device": "wap",
+                    "prefer_code_type": 1,
+                }),
+            )
+            .await?;
+        let play_url: IntlPlayUrl = serde_json::from_value(data["playurl"].clone())?;
+        let play_url = play_url.into_video_play_url();
+        if play_url.dash.video.is_empty() {
+            return Err(Error::StateError(
+                "没有可用的视频地址，可能需要登录或会员".to_string(),
+            ));
+        }
+        Ok(play_url)
+    }
+
+    /// bilibili.tv 剧集的外挂字幕列表
+    pub async fn fetch_intl_subtitles(
+        &self,
+        ep_id: i64,
+        locale: &str,
+    ) -> Result<Vec<IntlSubtitle>> {
+        let data: IntlSubtitleList = self
+            .get_intl_data(
+                "/web/v2/subtitle",
+                locale,
+                serde_json::json!({ "episode_id": ep_id }),
+            )
+            .await?;
+        Ok(data.into_subtitles())
+    }
+
+    /// 下载字幕，json 格式的字幕转换为 srt，返回的内容与 `IntlSubtitle::extension` 对应
+    pub async fn download_intl_subtitle(&self, subtitle: &IntlSubtitle) -> Result<String> {
+        let text = self
+            .download_resource(subtitle.url.as_str())
+            .await?
+            .error_for_status()?
+            .text()
+            .await?;
+        if subtitle.format != "ass" && text.trim_start().starts_with('{') {
+            let json: SubtitleJson = serde_json::from_str(&text)?;
+            Ok(json.to_srt())
+        } else {
+            Ok(text)
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct IntlSeason {
+    #[serde(deserialize_with = "de_id")]
+    pub season_id: i64,
+    #[serde(default)]
+    pub title: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub horizontal_cover: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct IntlEpisode {
+    #[serde(deserialize_with = "de_id")]
+    pub episode_id: i64,
+    /// 例如 E1
+    #[serde(default)]
+    pub title_display: String,
+    #[serde(default)]
+    pub long_title_display: String,
+    #[serde(default)]
+    pub cover: String,
+}
+
+impl IntlEpisode {
+    pub fn show_title(&self) -> String {
+        if self.long_title_display.is_empty() {
+            self.title_display.clone()
+        } else {
+            format!("{} {}", self.title_display, self.long_title_display)
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct IntlSections {
+    #[serde(default)]
+    sections: Vec<IntlSection>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct IntlSection {
+    #[serde(default)]
+    episodes: Vec<IntlEpisode>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct IntlSubtitle {
+    /// 例如 zh-Hans、en、th
+    pub lang_key: String,
+    /// 语言名称
+    pub lang: String,
+    /// 是否为机器翻译
+    pub is_machine: bool,
+    pub url: String,
+    /// json 或 ass
+    pub format: String,
+}
+
+impl IntlSubtitle {
+    /// 下载后的字幕文件扩展名，json 转换为 srt
+    pub fn extension(&self) -> &str {
+        if self.format == "ass" { "ass" } else { "srt" }
+    }
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct IntlSubtitleList {
+    /// 新版接口，分别提供 srt（实际为 json）和 ass 地址
+    #[serde(default)]
+    video_subtitle: Vec<IntlVideoSubtitle>,
+    /// 旧版接口，只有 json 地址
+    #[serde(default)]
+    subtitles: Vec<IntlLegacySubtitle>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct IntlVideoSubtitle {
+    #[serde(default)]
+    lang_key: String,
+    #[serde(default)]
+    lang: String,
+    #[serde(default)]
+    is_machine: bool,
+    srt: Option<IntlSubtitleUrl>,
+    ass: Option<IntlSubtitleUrl>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct IntlSubtitleUrl {
+    #[serde(default)]
+    url: String,
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct IntlLegacySubtitle {
+    #[serde(default)]
+    key: String,
+    #[serde(default)]
+    title: String,
+    #[serde(default)]
+    url: String,
+    #[serde(default)]
+    is_machine: bool,
+}
+
+impl IntlSubtitleList {
+    /// 优先使用 ass 字幕，保留样式
+    fn into_subtitles(self) -> Vec<IntlSubtitle> {
+        if self.video_subtitle.is_empty() {
+            return self
+                .subtitles
+                .into_iter()
+                .filter(|s| !s.url.is_empty())
+                .map(|s| IntlSubtitle {
+                    lang_key: s.key,
+                    lang: s.title,
+                    is_machine: s.is_machine,
+                    url: s.url,
+                    format: "json".to_string(),
+                })
+                .collect();
+        }
+        self.video_subtitle
+            .into_iter()
+            .filter_map(|s| {
+                let (url, format) = match (s.ass, s.srt) {
+                    (Some(ass), _) if !ass.url.is_empty() => (ass.url, "ass"),
+                    (_, Some(srt)) if !srt.url.is_empty() => (srt.url, "json"),
+                    _ => return None,
+                };
+                Some(IntlSubtitle {
+                    lang_key: s.lang_key,
+                    lang: s.lang,
+                    is_machine: s.is_machine,
+                    url,
+                    format: format.to_string(),
+                })
+            })
+            .collect()
+    }
+}
+
+/// json 格式的字幕，时间单位为秒
+#[derive(Deserialize, Debug, Default)]
+struct SubtitleJson {
+    #[serde(default)]
+    body: Vec<SubtitleLine>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct SubtitleLine {
+    from: f64,
+    to: f64,
+    #[serde(default)]
+    content: String,
+}
+
+impl SubtitleJson {
+    fn to_srt(&self) -> String {
+        let mut srt = String::new();
+        for (index, line) in self.body.iter().enumerate() {
+            srt.push_str(
+                format!(
+                    "{}\n{} --> {}\n{}\n\n",
+                    index + 1,
+                    srt_time(line.from),
+                    srt_time(line.to),
+                    line.content.trim()
+                )
+                .as_str(),
+            );
+        }
+        srt
+    }
+}
+
+fn srt_time(seconds: f64) -> String {
+    let millis = (seconds * 1000.0).round().max(0.0) as i64;
+    format!(
+        "{:02}:{:02}:{:02},{:03}",
+        millis / 3_600_000,
+        millis / 60_000 % 60,
+        millis / 1000 % 60,
+        millis % 1000
+    )
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct IntlPlayUrl {
+    /// 毫秒
+    #[serde(default)]
+    duration: i64,
+    #[serde(default)]
+    video: Vec<IntlVideo>,
+    #[serde(default)]
+    audio_resource: Vec<IntlResource>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct IntlVideo {
+    video_resource: IntlResource,
+    #[serde(default)]
+    stream_info: IntlStreamInfo,
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct IntlStreamInfo {
+    #[serde(default)]
+    quality: i64,
+    #[serde(default)]
+    desc_words: String,
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct IntlResource {
+    #[serde(default)]
+    quality: i64,
+    /// 需要会员或登录时为空
+    #[serde(default)]
+    url: String,
+    #[serde(default)]
+    backup_url: Vec<String>,
+    #[serde(default)]
+    bandwidth: i64,
+    #[serde(default)]
+    codecs: String,
+    #[serde(default)]
+    mime_type: String,
+    #[serde(default)]
+    width: i64,
+    #[serde(default)]
+    height: i64,
+    #[serde(default)]
+    frame_rate: String,
+    #[serde(default)]
+    codec_id: i64,
+}
+
+impl IntlResource {
+    fn into_media(self, id: i64) -> VideoMedia {
+        VideoMedia {
+            id,
+            base_url: self.url,
+            backup_url: self.backup_url,
+            bandwidth: self.bandwidth,
+            mime_type: self.mime_type,
+            codecs: self.codecs,
+            width: self.width,
+            height: self.height,
+            frame_rate: self.frame_rate,
+            codecid: self.codec_id,
+            ..Default::default()
+        }
+    }
+}
+
+impl IntlPlayUrl {
+    fn into_video_play_url(self) -> VideoPlayUrl {
+        let mut accept_quality = vec![];
+        let mut accept_description = vec![];
+        let mut video = vec![];
+        for v in self.video {
+            let quality = if v.stream_info.quality != 0 {
+                v.stream_info.quality
+            } else {
+                v.video_resource.quality
+            };
+            if !accept_quality.contains(&quality) {
+                accept_quality.push(quality);
+                accept_description.push(v.stream_info.desc_words.clone());
+            }
+            if !v.video_resource.url.is_empty() {
+                video.push(v.video_resource.into_media(quality));
+            }
+        }
+        let audio = self
+            .audio_resource
+            .into_iter()
+            .filter(|a| !a.url.is_empty())
+            .map(|a| {
+                let quality = a.quality;
+                a.into_media(quality)
+            })
+            .collect();
+        VideoPlayUrl {
+            quality: video.first().map(|v: &VideoMedia| v.id).unwrap_or_default(),
+            timelength: self.duration,
+            accept_description,
+            accept_quality,
+            dash: VideoDash {
+                duration: self.duration / 1000,
+                video,
+                audio,
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+}
+
+/// bilibili.tv 的 id 有时是字符串
+fn de_id<'de, D: Deserializer<'de>>(deserializer: D) -> std::result::Result<i64, D::Error> {
+    match serde_json::Value::deserialize(deserializer)? {
+        serde_json::Value::Number(n) => n
+            .as_i64()
+            .ok_or_else(|| serde::de::Error::custom("id 不是整数")),
+        serde_json::Value::String(s) => s.parse().map_err(serde::de::Error::custom),
+        other => Err(serde::de::Error::custom(format!("无效的 id: {}", other))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{IntlEpisode, IntlPlayUrl, IntlSubtitleList, SubtitleJson};
+
+    #[test]
+    fn test_intl_play_url() {
+        let play_url: IntlPlayUrl = serde_json::from_str(
+            r#"{"duration":1420000,"video":[
+            {"video_resource":{"url":"","quality":112,"codecs":"avc1.640032"},"stream_info":{"quality":112,"desc_words":"1080P (HD)","need_vip":true}},
+            {"video_resource":{"url":"https://upos-bstar1-mirrorakam.akamaized.net/a.m4s","backup_url":["https://b/a.m4s"],"bandwidth":420000,"codecs":"avc1.64001F","mime_type":"video/mp4","width":1280,"height":720,"frame_rate":"24","quality":64,"codec_id":7},"stream_info":{"quality":64,"desc_words":"720P"}}],
+            "audio_resource":[{"url":"https://upos-bstar1-mirrorakam.akamaized.net/b.m4s","backup_url":[],"bandwidth":130000,"codecs":"mp4a.40.2","mime_type":"audio/mp4","quality":30280}]}"#,
+        )
+        .unwrap();
+        let play_url = play_url.into_video_play_url();
+        assert_eq!(play_url.accept_quality, vec![112, 64]);
+        assert_eq!(play_url.dash.video.len(), 1);
+        assert_eq!(play_url.dash.video[0].id, 64);
+        assert_eq!(play_url.dash.video[0].backup_url, vec!["https://b/a.m4s"]);
+        assert_eq!(play_url.dash.audio[0].id, 30280);
+        assert_eq!(play_url.dash.duration, 1420);
+    }
+
+    #[test]
+    fn test_intl_subtitles() {
+        let list: IntlSubtitleList = serde_json::from_str(
+            r#"{"video_subtitle":[
+            {"lang_key":"zh-Hans","lang":"中文（简体）","is_machine":false,"srt":{"url":"https://s/zh.json"},"ass":{"url":"https://s/zh.ass"}},
+            {"lang_key":"en","lang":"English","is_machine":true,"srt":{"url":"https://s/en.json"},"ass":null}]}"#,
+        )
+        .unwrap();
+        let subtitles = list.into_subtitles();
+        assert_eq!(subtitles[0].format, "ass");
+        assert_eq!(subtitles[0].extension(), "ass");
+        assert_eq!(subtitles[1].format, "json");
+        assert_eq!(subtitles[1].extension(), "srt");
+
+        let json: SubtitleJson = serde_json::from_str(
+            r#"{"body":[{"from":1.5,"to":3.25,"content":"你好"},{"from":3661.0,"to":3662.0,"content":"世界 "}]}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            json.to_srt(),
+            "1\n00:00:01,500 --> 00:00:03,250\n你好\n\n2\n01:01:01,000 --> 01:01:02,000\n世界\n\n"
+        );
+
+        let episode: IntlEpisode =
+            serde_json::from_str(r#"{"episode_id":"2110623","title_display":"E1"}"#).unwrap();
+        assert_eq!(episode.episode_id, 2110623);
+        assert_eq!(episode.show_title(), "E1");
+    }
+}
@@ -1,16 +1,30 @@
+pub mod area;
+pub mod audio;
 pub mod auth;
+pub mod builder;
 pub mod client;
+pub mod cookie;
 pub mod download;
 pub mod error;
 pub mod fetcher;
+pub mod fingerprint;
+pub mod intl;
+pub mod live;
 pub mod parse;
+pub mod scheduler;
+pub mod stein;
 pub mod util;
+pub mod wbi;
 
 #[cfg(test)]
 mod tests;
 
+pub use area::Area;
+pub use builder::*;
 pub use client::*;
-pub use error::{BBDDError, BBDDResult};
+pub use cookie::{Cookie, CookieJar};
+pub use error::{ApiErrorKind, BBDDError, BBDDResult};
+pub use scheduler::RateLimitConfig;
 pub(crate) use error::{Error, Result};
 
 #[cfg(not(any(feature = "native-tls", feature = "rustls",)))]
@@ -0,0 +1,546 @@
+use crate::{BBDD, Error, Result};
+use futures::{SinkExt, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::io::Read;
+use std::time::Duration;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+
+const DANMU_INFO_URL: &str = "https://api.live.bilibili.com/xlive/web-room/v1/index/getDanmuInfo";
+
+/// 心跳
+pub const OP_HEARTBEAT: u32 = 2;
+/// 心跳回复，内容为人气值
+pub const OP_HEARTBEAT_REPLY: u32 = 3;
+/// 弹幕、礼物等消息
+pub const OP_MESSAGE: u32 = 5;
+/// 进入房间
+pub const OP_AUTH: u32 = 7;
+/// 进入房间的回复
+pub const OP_AUTH_REPLY: u32 = 8;
+
+const HEADER_LEN: usize = 16;
+const PROTOVER_ZLIB: u16 = 2;
+const PROTOVER_BROTLI: u16 = 3;
+
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(30);
+const AUTH_TIMEOUT: Duration = Duration::from_secs(10);
+
+impl BBDD {
+    /// 弹幕服务器地址和进入房间使用的 token，room_id 需要是长号
+    pub async fn live_danmu_info(&self, room_id: i64) -> Result<LiveDanmuInfo> {
+        let params = [("id", room_id.to_string()), ("type", "0".to_string())]
+            .map(|(k, v)| (k.to_string(), v));
+        self.get_wbi_data(DANMU_INFO_URL, &params).await
+    }
+
+    /// 连接直播间的弹幕服务器，依次尝试返回的各个服务器
+    pub async fn connect_live_chat(&self, room_id: i64) -> Result<LiveChatClient> {
+        let info = self.live_danmu_info(room_id).await?;
+        let (uid, buvid) = {
+            let jar = self.cookie.read().unwrap();
+            let uid = jar
+                .get("DedeUserID")
+                .and_then(|c| c.value.parse().ok())
+                .unwrap_or_default();
+            let buvid = jar
+                .get("buvid3")
+                .map(|c| c.value.clone())
+                .unwrap_or_default();
+            (uid, buvid)
+        };
+        let auth = LiveChatAuth {
+            uid,
+            roomid: room_id,
+            protover: PROTOVER_BROTLI as i64,
+            buvid,
+            platform: "web".to_string(),
+            r#type: 2,
+            key: info.token,
+        };
+        let mut last_err = None;
+        for host in &info.host_list {
+            let url = format!("wss://{}:{}/sub", host.host, host.wss_port);
+            match LiveChatClient::connect(url.as_str(), &auth, &self.ua).await {
+                Ok(client) => return Ok(client),
+                Err(e) => {
+                    tracing::warn!("连接弹幕服务器 {} 失败: {}", url, e);
+                    last_err = Some(e);
+                }
+            }
+        }
+        Err(last_err.unwrap_or_else(|| Error::StateError("没有可用的弹幕服务器".to_string())))
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct LiveDanmuInfo {
+    pub token: String,
+    #[serde(default)]
+    pub host_list: Vec<LiveDanmuHost>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct LiveDanmuHost {
+    pub host: String,
+    #[serde(default)]
+    pub port: i64,
+    #[serde(default)]
+    pub wss_port: i64,
+    #[serde(default)]
+    pub ws_port: i64,
+}
+
+/// 进入房间时发送的认证信息
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct LiveChatAuth {
+    /// 未登录时为 0
+    pub uid: i64,
+    pub roomid: i64,
+    pub protover: i64,
+    pub buvid: String,
+    pub platform: String,
+    pub r#type: i64,
+    pub key: String,
+}
+
+/// 解压之后的一个数据包
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LivePacket {
+    pub op: u32,
+    pub body: Vec<u8>,
+}
+
+/// 16 字节的包头：总长度、包头长度、协议版本、操作码、序号，均为大端
+pub fn encode_packet(op: u32, body: &[u8]) -> Vec<u8> {
+    let mut packet = Vec::with_capacity(HEADER_LEN + body.len());
+    packet.extend_from_slice(&((HEADER_LEN + body.len()) as u32).to_be_bytes());
+    packet.extend_from_slice(&(HEADER_LEN as u16).to_be_bytes());
+    packet.extend_from_slice(&1u16.to_be_bytes());
+    packet.extend_from_slice(&op.to_be_bytes());
+    packet.extend_from_slice(&1u32.to_be_bytes());
+    packet.extend_from_slice(body);
+    packet
+}
+
+/// 拆分一条 WebSocket 消息中的数据包，zlib / brotli 压缩的包会解压后继续拆分
+pub fn decode_packets(data: &[u8]) -> Result<Vec<LivePacket>> {
+    let mut packets = vec![];
+    let mut pos = 0;
+    while pos < data.len() {
+        if data.len() - pos < HEADER_LEN {
+            return Err(Error::StateError("弹幕数据包不完整".to_string()));
+        }
+        let header = &data[pos..pos + HEADER_LEN];
+        let total = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize;
+        let header_len = u16::from_be_bytes([header[4], header[5]]) as usize;
+        let protover = u16::from_be_bytes([header[6], header[7]]);
+        let op = u32::from_be_bytes([header[8], header[9], header[10], header[11]]);
+        if total < header_len || header_len < HEADER_LEN || data.len() - pos < total {
+            return Err(Error::StateError("弹幕数据包长度错误".to_string()));
+        }
+        let body = &data[pos + header_len..pos + total];
+        match protover {
+            PROTOVER_ZLIB => {
+                let mut inflated = vec![];
+                flate2::read::ZlibDecoder::new(body)
+                    .read_to_end(&mut inflated)
+                    .map_err(|e| Error::StateError(format!("弹幕数据解压失败: {}", e)))?;
+                packets.extend(decode_packets(&inflated)?);
+            }
+            PROTOVER_BROTLI => {
+                let mut inflated = vec![];
+                brotli::Decompressor::new(body, 4096)
+                    .read_to_end(&mut inflated)
+                    .map_err(|e| Error::StateError(format!("弹幕数据解压失败: {}", e)))?;
+                packets.extend(decode_packets(&inflated)?);
+            }
+            _ => packets.push(LivePacket {
+                op,
+                body: body.to_vec(),
+            }),
+        }
+        pos += total;
+    }
+    Ok(packets)
+}
+
+/// 直播间的消息，未单独解析的命令保存在 Other 中
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type")]
+pub enum LiveEvent {
+    Danmaku(LiveDanmaku),
+    Gift(LiveGift),
+    SuperChat(LiveSuperChat),
+    /// 心跳回复中的人气值
+    Popularity {
+        value: u32,
+    },
+    Other {
+        cmd: String,
+        data: serde_json::Value,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct LiveDanmaku {
+    pub uid: i64,
+    pub uname: String,
+    pub text: String,
+    pub mode: i64,
+    pub font_size: i64,
+    pub color: i64,
+    /// 毫秒
+    pub timestamp: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct LiveGift {
+    pub uid: i64,
+    pub uname: String,
+    pub gift_name: String,
+    pub num: i64,
+    /// 单价，金瓜子时 1000 为 1 元
+    pub price: i64,
+    /// gold 或 silver
+    pub coin_type: String,
+    /// 秒
+    pub timestamp: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct LiveSuperChat {
+    pub uid: i64,
+    pub uname: String,
+    pub message: String,
+    /// 元
+    pub price: i64,
+    /// 秒
+    pub start_time: i64,
+}
+
+impl LiveEvent {
+    /// 解析 OP_MESSAGE 的内容
+    pub fn from_json(json: &serde_json::Value) -> LiveEvent {
+        let cmd = json["cmd"].as_str().unwrap_or_default();
+        let data = &json["data"];
+        let str_of = |v: &serde_json::Value| v.as_str().unwrap_or_default().to_string();
+        if cmd.starts_with("DANMU_MSG") {
+            let info = &json["info"];
+            LiveEvent::Danmaku(LiveDanmaku {
+                uid: info[2][0].as_i64().unwrap_or_default(),
+                uname: str_of(&info[2][1]),
+                text: str_of(&info[1]),
+                mode: info[0][1].as_i64().unwrap_or(1),
+                font_size: info[0][2].as_i64().unwrap_or(25),
+                color: info[0][3].as_i64().unwrap_or(0xffffff),
+                timestamp: info[0][4].as_i64().unwrap_or_default(),
+            })
+        } else if cmd == "SEND_GIFT" {
+            LiveEvent::Gift(LiveGift {
+                uid: data["uid"].as_i64().unwrap_or_default(),
+                uname: str_of(&data["uname"]),
+                gift_name: str_of(&data["giftName"]),
+                num: data["num"].as_i64().unwrap_or_default(),
+                price: data["price"].as_i64().unwrap_or_default(),
+                coin_type: str_of(&data["coin_type"]),
+                timestamp: data["timestamp"].as_i64().unwrap_or_default(),
+            })
+        } else if cmd == "SUPER_CHAT_MESSAGE" {
+            LiveEvent::SuperChat(LiveSuperChat {
+                uid: data["uid"].as_i64().unwrap_or_default(),
+                uname: str_of(&data["user_info"]["uname"]),
+                message: str_of(&data["message"]),
+                price: data["price"].as_i64().unwrap_or_default(),
+                start_time: data["start_time"].as_i64().unwrap_or_default(),
+            })
+        } else {
+            LiveEvent::Other {
+                cmd: cmd.to_string(),
+                data: json.clone(),
+            }
+        }
+    }
+
+    /// B站弹幕 XML 格式的一行，offset 为相对于录制开始的秒数，其它消息返回 None
+    pub fn to_xml(&self, offset: f64) -> Option<String> {
+        match self {
+            LiveEvent::Danmaku(d) => Some(format!(
+                r#"<d p="{:.3},{},{},{},{},0,{},0" user="{}">{}</d>"#,
+                offset,
+                d.mode,
+                d.font_size,
+                d.color,
+                d.timestamp,
+                d.uid,
+                xml_escape(&d.uname),
+                xml_escape(&d.text)
+            )),
+            LiveEvent::Gift(g) => Some(format!(
+                r#"<gift ts="{:.3}" uid="{}" user="{}" giftname="{}" giftcount="{}" price="{}"></gift>"#,
+                offset,
+                g.uid,
+                xml_escape(&g.uname),
+                xml_escape(&g.gift_name),
+                g.num,
+                g.price
+            )),
+            LiveEvent::SuperChat(s) => Some(format!(
+                r#"<sc ts="{:.3}" uid="{}" user="{}" price="{}" time="{}">{}</sc>"#,
+                offset,
+                s.uid,
+                xml_escape(&s.uname),
+                s.price,
+                s.start_time,
+                xml_escape(&s.message)
+            )),
+            _ => None,
+        }
+    }
+}
+
+fn xml_escape(text: &str) -> String {
+    text.chars()
+        .filter(|c| !c.is_control())
+        .map(|c| match c {
+            '&' => "&amp;".to_string(),
+            '<' => "&lt;".to_string(),
+            '>' => "&gt;".to_string(),
+            '"' => "&quot;".to_string(),
+            '\'' => "&apos;".to_string(),
+            c => c.to_string(),
+        })
+        .collect()
+}
+
+type WsStream =
+    tokio_tungstenite::WebSocketStream<tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>>;
+
+/// 弹幕服务器的连接，自动发送心跳
+pub struct LiveChatClient {
+    ws: WsStream,
+    heartbeat: tokio::time::Interval,
+    pending: VecDeque<LiveEvent>,
+}
+
+impl LiveChatClient {
+    /// 连接并进入房间，服务器拒绝时返回 ApiError
+    pub async fn connect(url: &str, auth: &LiveChatAuth, ua: &str) -> Result<LiveChatClient> {
+        let mut request = url.into_client_request().map_err(ws_error)?;
+        if let Ok(ua) = ua.parse() {
+            request.headers_mut().insert("User-Agent", ua);
+        }
+        let (mut ws, _) = tokio_tungstenite::connect_async(request)
+            .await
+            .map_err(ws_error)?;
+        let body = serde_json::to_vec(auth)?;
+        ws.send(Message::Binary(encode_packet(OP_AUTH, &body).into()))
+            .await
+            .map_err(ws_error)?;
+        let reply = tokio::time::timeout(AUTH_TIMEOUT, wait_auth_reply(&mut ws))
+            .await
+            .map_err(|_| Error::StateError("进入直播间超时".to_string()))??;
+        let code = reply["code"].as_i64().unwrap_or(-1);
+        if code != 0 {
+            return Err(Error::ApiError {
+                code: code as i32,
+                message: "进入直播间失败".to_string(),
+            });
+        }
+        Ok(LiveChatClient {
+            ws,
+            heartbeat: tokio::time::interval(HEARTBEAT_INTERVAL),
+            pending: VecDeque::new(),
+        })
+    }
+
+    /// 下一条消息，连接关闭时返回 None
+    pub async fn next_event(&mut self) -> Result<Option<LiveEvent>> {
+        loop {
+            if let Some(event) = self.pending.pop_front() {
+                return Ok(Some(event));
+            }
+            tokio::select! {
+                _ = self.heartbeat.tick() => {
+                    let packet = encode_packet(OP_HEARTBEAT, b"[object Object]");
+                    self.ws
+                        .send(Message::Binary(packet.into()))
+                        .await
+                        .map_err(ws_error)?;
+                }
+                message = self.ws.next() => match message {
+                    None | Some(Ok(Message::Close(_))) => return Ok(None),
+                    Some(Err(e)) => return Err(ws_error(e)),
+                    Some(Ok(Message::Binary(data))) => {
+                        for packet in decode_packets(&data)? {
+                            if let Some(event) = packet_event(&packet) {
+                                self.pending.push_back(event);
+                            }
+                        }
+                    }
+                    Some(Ok(_)) => {}
+                },
+            }
+        }
+    }
+
+    pub async fn close(mut self) -> Result<()> {
+        self.ws.close(None).await.map_err(ws_error)
+    }
+}
+
+async fn wait_auth_reply(ws: &mut WsStream) -> Result<serde_json::Value> {
+    while let Some(message) = ws.next().await {
+        if let Message::Binary(data) = message.map_err(ws_error)? {
+            for packet in decode_packets(&data)? {
+                if packet.op == OP_AUTH_REPLY {
+                    return Ok(serde_json::from_slice(&packet.body)?);
+                }
+            }
+        }
+    }
+    Err(Error::StateError("弹幕服务器断开了连接".to_string()))
+}
+
+fn packet_event(packet: &LivePacket) -> Option<LiveEvent> {
+    match packet.op {
+        OP_HEARTBEAT_REPLY => {
+            let value = packet.body.get(..4)?;
+            Some(LiveEvent::Popularity {
+                value: u32::from_be_bytes([value[0], value[1], value[2], value[3]]),
+            })
+        }
+        OP_MESSAGE => match serde_json::from_slice(&packet.body) {
+            Ok(json) => Some(LiveEvent::from_json(&json)),
+            Err(e) => {
+                tracing::warn!("无法解析弹幕消息: {}", e);
+                None
+            }
+        },
+        _ => None,
+    }
+}
+
+fn ws_error(e: tokio_tungstenite::tungstenite::Error) -> Error {
+    Error::StateError(format!("弹幕连接错误: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    const DANMU_MSG: &str = r#"{"cmd":"DANMU_MSG","info":[[0,1,25,16777215,1700000000123,0,0,"",0,0,0,"",0],"你好<3",[42,"观众",0,0,0,10000,1,""]]}"#;
+    const SEND_GIFT: &str = r#"{"cmd":"SEND_GIFT","data":{"uid":7,"uname":"送礼","giftName":"辣条","num":3,"price":100,"coin_type":"silver","timestamp":1700000001}}"#;
+
+    fn zlib(data: &[u8]) -> Vec<u8> {
+        let mut encoder =
+            flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn brotli(data: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        {
+            let mut writer = brotli::CompressorWriter::new(&mut out, 4096, 5, 22);
+            writer.write_all(data).unwrap();
+        }
+        out
+    }
+
+    fn compressed(protover: u16, data: &[u8]) -> Vec<u8> {
+        let body = if protover == PROTOVER_ZLIB {
+            zlib(data)
+        } else {
+            brotli(data)
+        };
+        let mut packet = encode_packet(OP_MESSAGE, &body);
+        packet[6..8].copy_from_slice(&protover.to_be_bytes());
+        packet
+    }
+
+    #[test]
+    fn test_decode_packets() {
+        let mut inner = encode_packet(OP_MESSAGE, DANMU_MSG.as_bytes());
+        inner.extend(encode_packet(OP_MESSAGE, SEND_GIFT.as_bytes()));
+        for protover in [PROTOVER_ZLIB, PROTOVER_BROTLI] {
+            let mut data = compressed(protover, &inner);
+            data.extend(encode_packet(OP_HEARTBEAT_REPLY, &1234u32.to_be_bytes()));
+            let events: Vec<_> = decode_packets(&data)
+                .unwrap()
+                .iter()
+                .filter_map(packet_event)
+                .collect();
+            assert_eq!(events.len(), 3);
+            let LiveEvent::Danmaku(danmaku) = &events[0] else {
+                panic!("Expected danmaku, got {:?}", events[0]);
+            };
+            assert_eq!(danmaku.uid, 42);
+            assert_eq!(danmaku.text, "你好<3");
+            assert!(
+                matches!(&events[1], LiveEvent::Gift(g) if g.gift_name == "辣条" && g.num == 3)
+            );
+            assert_eq!(events[2], LiveEvent::Popularity { value: 1234 });
+        }
+        assert!(decode_packets(&[0, 0, 0, 32, 0, 16]).is_err());
+    }
+
+    #[test]
+    fn test_event_to_xml() {
+        let event = LiveEvent::from_json(&serde_json::from_str(DANMU_MSG).unwrap());
+        assert_eq!(
+            event.to_xml(1.5).unwrap(),
+            r#"<d p="1.500,1,25,16777215,1700000000123,0,42,0" user="观众">你好&lt;3</d>"#
+        );
+        let event = LiveEvent::from_json(&serde_json::json!({"cmd": "INTERACT_WORD"}));
+        assert!(event.to_xml(0.0).is_none());
+    }
+
+    /// 使用本地的 WebSocket 服务代替弹幕服务器
+    #[tokio::test]
+    async fn test_live_chat_client() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut ws = tokio_tungstenite::accept_async(stream).await.unwrap();
+            let Some(Ok(Message::Binary(data))) = ws.next().await else {
+                panic!("Expected auth packet");
+            };
+            let packets = decode_packets(&data).unwrap();
+            assert_eq!(packets[0].op, OP_AUTH);
+            let auth: LiveChatAuth = serde_json::from_slice(&packets[0].body).unwrap();
+            assert_eq!((auth.roomid, auth.key.as_str()), (1, "token"));
+            let reply = encode_packet(OP_AUTH_REPLY, br#"{"code":0}"#);
+            ws.send(Message::Binary(reply.into())).await.unwrap();
+            let message = compressed(
+                PROTOVER_BROTLI,
+                &encode_packet(OP_MESSAGE, DANMU_MSG.as_bytes()),
+            );
+            ws.send(Message::Binary(message.into())).await.unwrap();
+            // 等待客户端的心跳后关闭连接
+            while let Some(Ok(message)) = ws.next().await {
+                if let Message::Binary(data) = message {
+                    assert_eq!(decode_packets(&data).unwrap()[0].op, OP_HEARTBEAT);
+                    break;
+                }
+            }
+            ws.close(None).await.unwrap();
+        });
+        let auth = LiveChatAuth {
+            roomid: 1,
+            key: "token".to_string(),
+            ..Default::default()
+        };
+        let url = format!("ws://{}/sub", addr);
+        let mut client = LiveChatClient::connect(url.as_str(), &auth, "test")
+            .await
+            .unwrap();
+        let event = client.next_event().await.unwrap().unwrap();
+        assert!(matches!(event, LiveEvent::Danmaku(d) if d.uname == "观众"));
+        assert!(client.next_event().await.unwrap().is_none());
+        server.await.unwrap();
+    }
+}
@@ -0,0 +1,418 @@
+#[cfg(feature = "live")]
+pub mod chat;
+
+use crate::{BBDD, Error, Result};
+use serde::{Deserialize, Serialize};
+
+const ROOM_INFO_URL: &str = "https://api.live.bilibili.com/room/v1/Room/get_info";
+
+const PLAY_INFO_URL: &str = "https://api.live.bilibili.com/xlive/web-room/v2/index/getRoomPlayInfo";
+
+/// 直播中
+pub const LIVE_STATUS_LIVE: i64 = 1;
+
+/// 原画
+pub const LIVE_QN_ORIGINAL: i64 = 10000;
+
+/// CDN 节点之间的重定向次数上限
+const MAX_REDIRECTS: usize = 5;
+
+impl BBDD {
+    /// 直播间信息，room_id 可以是短号
+    pub async fn fetch_live_room_info(&self, room_id: i64) -> Result<LiveRoomInfo> {
+        let url = format!("{}?room_id={}", ROOM_INFO_URL, room_id);
+        self.get_data(url.as_str(), None).await
+    }
+
+    /// 直播流地址，同时请求 FLV 和 HLS（ts / fmp4）
+    pub async fn live_play_info(&self, room_id: i64, qn: i64) -> Result<LivePlayInfo> {
+        let url = format!(
+            "{}?room_id={}&protocol=0,1&format=0,1,2&codec=0,1&qn={}&platform=web&ptype=8&dolby=5&panorama=1",
+            PLAY_INFO_URL, room_id, qn
+        );
+        self.get_data(url.as_str(), None).await
+    }
+
+    /// 请求直播流或 HLS 播放列表，跟随 CDN 节点的重定向，响应的 url 为最终地址
+    pub async fn get_live_stream(&self, url: &str) -> Result<reqwest::Response> {
+        let mut url = url.to_string();
+        for _ in 0..=MAX_REDIRECTS {
+            let response = self
+                .media_agent
+                .get(url.as_str())
+                .header("Referer", "https://live.bilibili.com/")
+                .header("Origin", "https://live.bilibili.com")
+                .header("User-Agent", &self.ua)
+                .send()
+                .await?;
+            if !response.status().is_redirection() {
+                return Ok(response.error_for_status()?);
+            }
+            let location = response
+                .headers()
+                .get(reqwest::header::LOCATION)
+                .and_then(|v| v.to_str().ok())
+                .ok_or_else(|| Error::StateError("直播流重定向缺少 Location".to_string()))?;
+            url = response
+                .url()
+                .join(location)
+                .map_err(|e| Error::StateError(format!("直播流重定向地址错误: {}", e)))?
+                .to_string();
+        }
+        Err(Error::StateError("直播流重定向次数过多".to_string()))
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct LiveRoomInfo {
+    pub room_id: i64,
+    #[serde(default)]
+    pub short_id: i64,
+    #[serde(default)]
+    pub uid: i64,
+    #[serde(default)]
+    pub title: String,
+    /// 0 未开播，1 直播中，2 轮播中
+    #[serde(default)]
+    pub live_status: i64,
+    #[serde(default)]
+    pub live_time: String,
+    #[serde(default)]
+    pub area_name: String,
+    #[serde(default)]
+    pub parent_area_name: String,
+    #[serde(default)]
+    pub user_cover: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct LivePlayInfo {
+    pub room_id: i64,
+    #[serde(default)]
+    pub live_status: i64,
+    /// 未开播时为 null
+    #[serde(default)]
+    pub playurl_info: Option<LivePlayUrlInfo>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct LivePlayUrlInfo {
+    pub playurl: LivePlayUrl,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct LivePlayUrl {
+    #[serde(default)]
+    pub g_qn_desc: Vec<LiveQnDesc>,
+    #[serde(default)]
+    pub stream: Vec<LiveStream>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct LiveQnDesc {
+    pub qn: i64,
+    pub desc: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct LiveStream {
+    /// http_stream 或 http_hls
+    pub protocol_name: String,
+    #[serde(default)]
+    pub format: Vec<LiveFormat>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct LiveFormat {
+    /// flv、ts 或 fmp4
+    pub format_name: String,
+    #[serde(default)]
+    pub codec: Vec<LiveCodec>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct LiveCodec {
+    /// avc 或 hevc
+    pub codec_name: String,
+    pub current_qn: i64,
+    #[serde(default)]
+    pub accept_qn: Vec<i64>,
+    pub base_url: String,
+    #[serde(default)]
+    pub url_info: Vec<LiveUrlInfo>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct LiveUrlInfo {
+    pub host: String,
+    pub extra: String,
+    #[serde(default)]
+    pub stream_ttl: i64,
+}
+
+/// 展开后的一个直播流，urls 为同一个流的不同 CDN 节点
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct LiveSource {
+    pub protocol_name: String,
+    pub format_name: String,
+    pub codec_name: String,
+    pub qn: i64,
+    pub urls: Vec<String>,
+}
+
+impl LivePlayInfo {
+    /// 所有可用的直播流
+    pub fn sources(&self) -> Vec<LiveSource> {
+        let Some(info) = &self.playurl_info else {
+            return vec![];
+        };
+        let mut sources = vec![];
+        for stream in &info.playurl.stream {
+            for format in &stream.format {
+                for codec in &format.codec {
+                    sources.push(LiveSource {
+                        protocol_name: stream.protocol_name.clone(),
+                        format_name: format.format_name.clone(),
+                        codec_name: codec.codec_name.clone(),
+                        qn: codec.current_qn,
+                        urls: codec
+                            .url_info
+                            .iter()
+                            .map(|u| format!("{}{}{}", u.host, codec.base_url, u.extra))
+                            .collect(),
+                    });
+                }
+            }
+        }
+        sources
+    }
+
+    /// 清晰度的名称，例如 10000 为原画
+    pub fn qn_desc(&self, qn: i64) -> String {
+        self.playurl_info
+            .as_ref()
+            .and_then(|info| info.playurl.g_qn_desc.iter().find(|d| d.qn == qn))
+            .map(|d| d.desc.clone())
+            .unwrap_or_else(|| qn.to_string())
+    }
+}
+
+pub const FLV_TAG_AUDIO: u8 = 8;
+pub const FLV_TAG_VIDEO: u8 = 9;
+pub const FLV_TAG_SCRIPT: u8 = 18;
+
+/// FLV 文件头（包括 PreviousTagSize0）或者一个完整的 tag
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FlvPacket {
+    Header(Vec<u8>),
+    Tag(FlvTag),
+}
+
+/// 一个完整的 FLV tag，包括 11 字节的 tag 头和末尾的 PreviousTagSize
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FlvTag {
+    pub bytes: Vec<u8>,
+}
+
+impl FlvTag {
+    pub fn tag_type(&self) -> u8 {
+        self.bytes[0] & 0x1f
+    }
+
+    /// 毫秒
+    pub fn timestamp(&self) -> u32 {
+        let b = &self.bytes;
+        u32::from_be_bytes([b[7], b[4], b[5], b[6]])
+    }
+
+    pub fn set_timestamp(&mut self, timestamp: u32) {
+        let [ext, b1, b2, b3] = timestamp.to_be_bytes();
+        self.bytes[4..8].copy_from_slice(&[b1, b2, b3, ext]);
+    }
+
+    fn data(&self) -> &[u8] {
+        &self.bytes[11..self.bytes.len() - 4]
+    }
+
+    pub fn is_keyframe(&self) -> bool {
+        self.tag_type() == FLV_TAG_VIDEO && self.data().first().is_some_and(|b| b >> 4 == 1)
+    }
+
+    /// AVC / HEVC 的解码配置或者 AAC 的 AudioSpecificConfig，切分文件时需要重新写入
+    pub fn is_sequence_header(&self) -> bool {
+        let data = self.data();
+        match (self.tag_type(), data) {
+            (FLV_TAG_VIDEO, [flags, 0, ..]) => matches!(flags & 0x0f, 7 | 12),
+            (FLV_TAG_AUDIO, [flags, 0, ..]) => flags >> 4 == 10,
+            _ => false,
+        }
+    }
+}
+
+/// 将直播流的数据块切分为 FLV 文件头和完整的 tag，不完整的数据留到下一次
+#[derive(Debug, Default)]
+pub struct FlvParser {
+    buf: Vec<u8>,
+    header_done: bool,
+}
+
+impl FlvParser {
+    pub fn push(&mut self, chunk: &[u8]) -> Result<Vec<FlvPacket>> {
+        self.buf.extend_from_slice(chunk);
+        let mut packets = vec![];
+        let mut pos = 0;
+        if !self.header_done {
+            if self.buf.len() < 9 {
+                return Ok(packets);
+            }
+            if &self.buf[0..3] != b"FLV" {
+                return Err(Error::StateError("不是FLV格式的直播流".to_string()));
+            }
+            let header_len =
+                u32::from_be_bytes([self.buf[5], self.buf[6], self.buf[7], self.buf[8]]) as usize;
+            if self.buf.len() < header_len + 4 {
+                return Ok(packets);
+            }
+            packets.push(FlvPacket::Header(self.buf[..header_len + 4].to_vec()));
+            pos = header_len + 4;
+            self.header_done = true;
+        }
+        while self.buf.len() - pos >= 11 {
+            let tag_type = self.buf[pos] & 0x1f;
+            if !matches!(tag_type, FLV_TAG_AUDIO | FLV_TAG_VIDEO | FLV_TAG_SCRIPT) {
+                return Err(Error::StateError(format!("FLV tag 类型错误: {}", tag_type)));
+            }
+            let data_len =
+                u32::from_be_bytes([0, self.buf[pos + 1], self.buf[pos + 2], self.buf[pos + 3]])
+                    as usize;
+            let tag_len = 11 + data_len + 4;
+            if self.buf.len() - pos < tag_len {
+                break;
+            }
+            packets.push(FlvPacket::Tag(FlvTag {
+                bytes: self.buf[pos..pos + tag_len].to_vec(),
+            }));
+            pos += tag_len;
+        }
+        self.buf.drain(..pos);
+        Ok(packets)
+    }
+}
+
+/// HLS 媒体播放列表
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct M3u8Playlist {
+    pub target_duration: f64,
+    pub media_sequence: u64,
+    /// fmp4 的初始化分片
+    pub map_uri: Option<String>,
+    pub segments: Vec<M3u8Segment>,
+    /// 出现 #EXT-X-ENDLIST，直播已结束
+    pub ended: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct M3u8Segment {
+    pub sequence: u64,
+    pub duration: f64,
+    pub uri: String,
+}
+
+/// 解析 HLS 媒体播放列表，忽略不认识的标签
+pub fn parse_m3u8(text: &str) -> Result<M3u8Playlist> {
+    let mut lines = text.lines().map(str::trim).filter(|l| !l.is_empty());
+    if lines.next() != Some("#EXTM3U") {
+        return Err(Error::StateError("不是m3u8格式的播放列表".to_string()));
+    }
+    let mut playlist = M3u8Playlist::default();
+    let mut duration = None;
+    for line in lines {
+        if let Some(value) = line.strip_prefix("#EXT-X-TARGETDURATION:") {
+            playlist.target_duration = value.parse().unwrap_or_default();
+        } else if let Some(value) = line.strip_prefix("#EXT-X-MEDIA-SEQUENCE:") {
+            playlist.media_sequence = value
+                .parse()
+                .map_err(|_| Error::StateError(format!("m3u8 序号错误: {}", value)))?;
+        } else if let Some(value) = line.strip_prefix("#EXT-X-MAP:") {
+            playlist.map_uri = value
+                .split(',')
+                .find_map(|attr| attr.trim().strip_prefix("URI="))
+                .map(|uri| uri.trim_matches('"').to_string());
+        } else if let Some(value) = line.strip_prefix("#EXTINF:") {
+            duration = value.split(',').next().and_then(|d| d.parse().ok());
+        } else if line == "#EXT-X-ENDLIST" {
+            playlist.ended = true;
+        } else if !line.starts_with('#') {
+            playlist.segments.push(M3u8Segment {
+                sequence: playlist.media_sequence + playlist.segments.len() as u64,
+                duration: duration.take().unwrap_or_default(),
+                uri: line.to_string(),
+            });
+        }
+    }
+    Ok(playlist)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FLV_TAG_VIDEO, FlvPacket, FlvParser, parse_m3u8};
+
+    fn tag(tag_type: u8, timestamp: u32, data: &[u8]) -> Vec<u8> {
+        let mut bytes = vec![tag_type];
+        bytes.extend_from_slice(&(data.len() as u32).to_be_bytes()[1..]);
+        let ts = timestamp.to_be_bytes();
+        bytes.extend_from_slice(&[ts[1], ts[2], ts[3], ts[0], 0, 0, 0]);
+        bytes.extend_from_slice(data);
+        bytes.extend_from_slice(&(11 + data.len() as u32).to_be_bytes());
+        bytes
+    }
+
+    #[test]
+    fn test_flv_parser() {
+        let mut stream = b"FLV\x01\x05\x00\x00\x00\x09\x00\x00\x00\x00".to_vec();
+        stream.extend(tag(FLV_TAG_VIDEO, 0, &[0x17, 0, 0, 0, 0]));
+        stream.extend(tag(FLV_TAG_VIDEO, 0x01020304, &[0x17, 1, 0, 0, 0, 0xaa]));
+        stream.extend(tag(FLV_TAG_VIDEO, 40, &[0x27, 1, 0, 0, 0, 0xbb]));
+        let mut parser = FlvParser::default();
+        let mut packets = vec![];
+        // 按任意位置切分的数据块
+        for chunk in stream.chunks(7) {
+            packets.extend(parser.push(chunk).unwrap());
+        }
+        assert_eq!(packets.len(), 4);
+        assert!(matches!(&packets[0], FlvPacket::Header(h) if h.len() == 13));
+        let tags: Vec<_> = packets[1..]
+            .iter()
+            .map(|p| match p {
+                FlvPacket::Tag(tag) => tag.clone(),
+                _ => panic!("Expected tag"),
+            })
+            .collect();
+        assert!(tags[0].is_sequence_header() && tags[0].is_keyframe());
+        assert!(!tags[1].is_sequence_header() && tags[1].is_keyframe());
+        assert!(!tags[2].is_keyframe());
+        assert_eq!(tags[1].timestamp(), 0x01020304);
+        let mut shifted = tags[1].clone();
+        shifted.set_timestamp(tags[1].timestamp() - 0x01000000);
+        assert_eq!(shifted.timestamp(), 0x020304);
+        assert!(parser.push(b"\x07garbage data").is_err());
+    }
+
+    #[test]
+    fn test_parse_m3u8() {
+        let playlist = parse_m3u8(
+            "#EXTM3U\n#EXT-X-VERSION:7\n#EXT-X-MEDIA-SEQUENCE:1200\n#EXT-X-TARGETDURATION:1\n\
+            #EXT-X-MAP:URI=\"h1700000000.m4s\"\n#EXTINF:1.00,a|b\n1200.m4s\n#EXT-X-BILI-AUX:x\n\
+            #EXTINF:0.98,\n1201.m4s?trid=1\n",
+        )
+        .unwrap();
+        assert_eq!(playlist.map_uri.as_deref(), Some("h1700000000.m4s"));
+        assert_eq!(playlist.target_duration, 1.0);
+        assert!(!playlist.ended);
+        assert_eq!(playlist.segments.len(), 2);
+        assert_eq!(playlist.segments[1].sequence, 1201);
+        assert_eq!(playlist.segments[1].duration, 0.98);
+        assert_eq!(playlist.segments[1].uri, "1201.m4s?trid=1");
+        assert!(parse_m3u8("<html>").is_err());
+    }
+}
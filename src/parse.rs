@@ -34,6 +34,27 @@ impl BBDD {
                     return Err(Error::ParamError("无法解析b23.tv链接".to_string()));
                 }
             }
+            if input.contains("live.bilibili.com/") {
+                let re = regex::Regex::new(r"live\.bilibili\.com/(?:h5/|blanc/)?(\d+)").unwrap();
+                if let Some(caps) = re.captures(input.as_str()) {
+                    let room_id = caps
+                        .get(1)
+                        .unwrap()
+                        .as_str()
+                        .parse::<i64>()
+                        .map_err(|e| Error::ParamError(format!("直播间号解析错误: {}", e)))?;
+                    return Ok(VideoType::LIVE(room_id));
+                }
+                return Err(Error::ParamError("无法解析直播间号".to_string()));
+            }
+            if input.contains("/audio/") {
+                let re = regex::Regex::new(r"/audio/(au|am)(\d+)").unwrap();
+                if let Some(caps) = re.captures(input.as_str()) {
+                    let kind = caps.get(1).unwrap().as_str();
+                    return audio_type(kind, caps.get(2).unwrap().as_str());
+                }
+                return Err(Error::ParamError("无法解析音频链接".to_string()));
+            }
             if input.contains("video/av") {
                 let re = regex::Regex::new(r"av(\d+)").unwrap();
                 if let Some(caps) = re.captures(input.as_str()) {
@@ -199,14 +220,14 @@ impl BBDD {
             }
             {
                 let re_global =
-                    regex::Regex::new(r"\.bilibili\.tv\/\w+\/play\/\d+\/(\d+)").unwrap();
+                    regex::Regex::new(r"\.bilibili\.tv/(?:[\w-]+/)?play/(\d+)(?:/(\d+))?").unwrap();
                 if let Some(caps) = re_global.captures(input.as_str()) {
-                    let ep_id = caps.get(1).unwrap().as_str();
-                    if let Ok(ep_id_num) = ep_id.parse::<i64>() {
-                        return Ok(VideoType::EPID(ep_id_num));
-                    } else {
-                        return Err(Error::ParamError("ep_id 不是数字".to_string()));
-                    }
+                    let season_id = caps.get(1).unwrap().as_str().parse::<i64>();
+                    let ep_id = caps.get(2).map(|m| m.as_str().parse::<i64>()).transpose();
+                    return match (season_id, ep_id) {
+                        (Ok(season_id), Ok(ep_id)) => Ok(VideoType::INTL { season_id, ep_id }),
+                        _ => Err(Error::ParamError("无法解析bilibili.tv链接".to_string())),
+                    };
                 }
                 let re_bangumi = regex::Regex::new(r"bangumi/media/(md\d+)").unwrap();
                 if let Some(caps) = re_bangumi.captures(input.as_str()) {
@@ -251,6 +272,9 @@ impl BBDD {
                 return Err(Error::ParamError("无法解析av号".to_string()));
             }
         }
+        if input.starts_with("au") || input.starts_with("am") {
+            return audio_type(&input[..2], &input[2..]);
+        }
         if input.starts_with("cheese/") {
             let re_ep = regex::Regex::new(r"cheese/ep(\d+)").unwrap();
             let re_ss = regex::Regex::new(r"cheese/ss(\d+)").unwrap();
@@ -376,6 +400,17 @@ impl BBDD {
     }
 }
 
+fn audio_type(kind: &str, id: &str) -> Result<VideoType> {
+    let id = id
+        .parse::<i64>()
+        .map_err(|e| Error::ParamError(format!("{}号解析错误: {}", kind, e)))?;
+    if kind == "am" {
+        Ok(VideoType::AUDIOMENU(id))
+    } else {
+        Ok(VideoType::AUDIO(id))
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 pub enum VideoType {
     AVID(i64),
@@ -385,6 +420,14 @@ pub enum VideoType {
     SERIESBIZID(String),
     MID(String),
     FAVID { fid: String, mid: String },
+    /// 直播间号，可能是短号
+    LIVE(i64),
+    /// bilibili.tv 番剧，ep_id 为空时下载整季
+    INTL { season_id: i64, ep_id: Option<i64> },
+    /// 音频 au 号
+    AUDIO(i64),
+    /// 音频歌单 am 号
+    AUDIOMENU(i64),
 }
 
 #[cfg(test)]
@@ -418,4 +461,63 @@ mod tests {
         let parse = client.parse_input("ss29325").await.unwrap();
         assert!(target.eq(&parse));
     }
+
+    #[tokio::test]
+    async fn test_parse_live() {
+        let client = &crate::tests::BBDD;
+        let parse = client
+            .parse_input("https://live.bilibili.com/21452505?live_from=85001")
+            .await
+            .unwrap();
+        assert_eq!(parse, VideoType::LIVE(21452505));
+        let parse = client
+            .parse_input("https://live.bilibili.com/h5/545068")
+            .await
+            .unwrap();
+        assert_eq!(parse, VideoType::LIVE(545068));
+    }
+
+    #[tokio::test]
+    async fn test_parse_audio() {
+        let client = &crate::tests::BBDD;
+        let parse = client
+            .parse_input("https://www.bilibili.com/audio/au15664?type=3")
+            .await
+            .unwrap();
+        assert_eq!(parse, VideoType::AUDIO(15664));
+        let parse = client
+            .parse_input("https://m.bilibili.com/audio/am10624")
+            .await
+            .unwrap();
+        assert_eq!(parse, VideoType::AUDIOMENU(10624));
+        let parse = client.parse_input("au15664").await.unwrap();
+        assert_eq!(parse, VideoType::AUDIO(15664));
+    }
+
+    #[tokio::test]
+    async fn test_parse_intl() {
+        let client = &crate::tests::BBDD;
+        let parse = client
+            .parse_input("https://www.bilibili.tv/en/play/1048837/11246489?bstar_from=bstar-web.pgc-video-detail.episode.all")
+            .await
+            .unwrap();
+        assert_eq!(
+            parse,
+            VideoType::INTL {
+                season_id: 1048837,
+                ep_id: Some(11246489)
+            }
+        );
+        let parse = client
+            .parse_input("https://www.bilibili.tv/zh/play/1048837")
+            .await
+            .unwrap();
+        assert_eq!(
+            parse,
+            VideoType::INTL {
+                season_id: 1048837,
+                ep_id: None
+            }
+        );
+    }
 }
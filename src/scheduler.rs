@@ -0,0 +1,179 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// 请求限速和风控重试的配置，按主机分别计算
+///
+/// ```
+/// let config = bbdd::RateLimitConfig {
+///     min_interval: std::time::Duration::from_millis(500),
+///     ..Default::default()
+/// };
+/// let bbdd = bbdd::BBDD::builder().rate_limit(config).build().unwrap();
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct RateLimitConfig {
+    /// 同一主机两次请求之间的最小间隔
+    pub min_interval: Duration,
+    /// 令牌桶每秒补充的令牌数，0 表示不使用令牌桶
+    pub requests_per_second: f64,
+    /// 令牌桶容量，即允许的突发请求数
+    pub burst: u32,
+    /// 遇到 -412 / -352 等风控错误时的最大重试次数
+    pub max_retries: u32,
+    /// 第一次重试前的等待时间，之后每次翻倍并加入随机抖动
+    pub backoff_base: Duration,
+    pub backoff_max: Duration,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        RateLimitConfig {
+            min_interval: Duration::from_millis(100),
+            requests_per_second: 4.0,
+            burst: 8,
+            max_retries: 3,
+            backoff_base: Duration::from_secs(2),
+            backoff_max: Duration::from_secs(30),
+        }
+    }
+}
+
+impl RateLimitConfig {
+    /// 不限速也不重试
+    pub fn disabled() -> Self {
+        RateLimitConfig {
+            min_interval: Duration::ZERO,
+            requests_per_second: 0.0,
+            burst: 0,
+            max_retries: 0,
+            backoff_base: Duration::ZERO,
+            backoff_max: Duration::ZERO,
+        }
+    }
+}
+
+pub(crate) struct Scheduler {
+    pub(crate) config: RateLimitConfig,
+    hosts: Mutex<HashMap<String, HostState>>,
+}
+
+struct HostState {
+    /// 可以为负数，表示已经被预约的令牌
+    tokens: f64,
+    updated: Instant,
+    next_allowed: Instant,
+}
+
+impl HostState {
+    fn new(config: &RateLimitConfig, now: Instant) -> Self {
+        HostState {
+            tokens: config.burst.max(1) as f64,
+            updated: now,
+            next_allowed: now,
+        }
+    }
+
+    /// 预约一次请求，返回需要等待的时间
+    fn reserve(&mut self, config: &RateLimitConfig, now: Instant) -> Duration {
+        let mut start = now.max(self.next_allowed);
+        if config.requests_per_second > 0.0 {
+            let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+            self.tokens = (self.tokens + elapsed * config.requests_per_second)
+                .min(config.burst.max(1) as f64);
+            self.updated = now;
+            if self.tokens < 1.0 {
+                let wait = (1.0 - self.tokens) / config.requests_per_second;
+                start = start.max(now + Duration::from_secs_f64(wait));
+            }
+            self.tokens -= 1.0;
+        }
+        self.next_allowed = start + config.min_interval;
+        start - now
+    }
+}
+
+impl Scheduler {
+    pub(crate) fn new(config: RateLimitConfig) -> Self {
+        Scheduler {
+            config,
+            hosts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// 等待直到可以向 host 发送请求
+    pub(crate) async fn acquire(&self, host: &str) {
+        let wait = {
+            let now = Instant::now();
+            let mut hosts = self.hosts.lock().unwrap();
+            hosts
+                .entry(host.to_string())
+                .or_insert_with(|| HostState::new(&self.config, now))
+                .reserve(&self.config, now)
+        };
+        if !wait.is_zero() {
+            tracing::debug!("Throttle {} for {:?}", host, wait);
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// 第 attempt 次重试前的等待时间，范围为指数退避时间的 50%~100%
+    pub(crate) fn backoff(&self, attempt: u32) -> Duration {
+        let delay = self
+            .config
+            .backoff_base
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.config.backoff_max);
+        delay.mul_f64(0.5 + rand::random::<f64>() * 0.5)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{HostState, RateLimitConfig, Scheduler};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_reserve_min_interval() {
+        let config = RateLimitConfig {
+            min_interval: Duration::from_millis(100),
+            requests_per_second: 0.0,
+            ..Default::default()
+        };
+        let now = Instant::now();
+        let mut state = HostState::new(&config, now);
+        assert_eq!(state.reserve(&config, now), Duration::ZERO);
+        assert_eq!(state.reserve(&config, now), Duration::from_millis(100));
+        assert_eq!(state.reserve(&config, now), Duration::from_millis(200));
+        let later = now + Duration::from_secs(1);
+        assert_eq!(state.reserve(&config, later), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_reserve_token_bucket() {
+        let config = RateLimitConfig {
+            min_interval: Duration::ZERO,
+            requests_per_second: 2.0,
+            burst: 2,
+            ..Default::default()
+        };
+        let now = Instant::now();
+        let mut state = HostState::new(&config, now);
+        assert_eq!(state.reserve(&config, now), Duration::ZERO);
+        assert_eq!(state.reserve(&config, now), Duration::ZERO);
+        assert_eq!(state.reserve(&config, now), Duration::from_millis(500));
+        assert_eq!(state.reserve(&config, now), Duration::from_millis(1000));
+    }
+
+    #[test]
+    fn test_backoff() {
+        let scheduler = Scheduler::new(RateLimitConfig::default());
+        for attempt in 0..10 {
+            let delay = scheduler.backoff(attempt);
+            let full = Duration::from_secs(2)
+                .saturating_mul(2u32.saturating_pow(attempt))
+                .min(Duration::from_secs(30));
+            assert!(delay >= full / 2 && delay <= full);
+        }
+    }
+}
@@ -0,0 +1,265 @@
+use crate::{BBDD, Error, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet, VecDeque};
+
+const PLAYER_V2_URL: &str = "https://api.bilibili.com/x/player/wbi/v2";
+
+const EDGE_INFO_URL: &str = "https://api.bilibili.com/x/stein/edgeinfo_v2";
+
+/// 互动视频的节点数上限，避免异常数据导致无限请求
+const MAX_NODES: usize = 1000;
+
+impl BBDD {
+    /// 互动视频剧情图的版本号，cid 为视频第一个节点（即 `VideoInfo.cid`）
+    pub async fn fetch_stein_graph_version(&self, aid: i64, cid: i64) -> Result<i64> {
+        let params = [("aid", aid), ("cid", cid)].map(|(k, v)| (k.to_string(), v.to_string()));
+        let json: serde_json::Value = self.get_wbi_data(PLAYER_V2_URL, &params).await?;
+        json["interaction"]["graph_version"]
+            .as_i64()
+            .ok_or_else(|| Error::StateError("该视频不是互动视频".to_string()))
+    }
+
+    /// 获取剧情图中的一个节点，edge_id 为 0 时获取第一个节点
+    pub async fn fetch_stein_edge(
+        &self,
+        aid: i64,
+        graph_version: i64,
+        edge_id: i64,
+    ) -> Result<SteinEdgeInfo> {
+        let mut url = format!(
+            "{}?aid={}&graph_version={}",
+            EDGE_INFO_URL, aid, graph_version
+        );
+        if edge_id != 0 {
+            url.push_str(format!("&edge_id={}", edge_id).as_str());
+        }
+        self.get_data(url.as_str(), None).await
+    }
+
+    /// 从第一个节点开始遍历全部选项，得到互动视频的完整剧情图
+    pub async fn fetch_stein_graph(&self, aid: i64, cid: i64) -> Result<SteinGraph> {
+        let graph_version = self.fetch_stein_graph_version(aid, cid).await?;
+        let root = self.fetch_stein_edge(aid, graph_version, 0).await?;
+        let mut graph = SteinGraph {
+            aid,
+            graph_version,
+            root_edge_id: root.edge_id,
+            nodes: BTreeMap::new(),
+            truncated: false,
+        };
+        let mut visited = HashSet::from([root.edge_id]);
+        let mut queue = VecDeque::from([(root, cid)]);
+        while let Some((edge, cid)) = queue.pop_front() {
+            let node = SteinNode::new(&edge, cid);
+            // 超过上限后不再获取新的节点，已经获取的节点仍然加入剧情图
+            for choice in &node.choices {
+                if graph.truncated || visited.contains(&choice.edge_id) {
+                    continue;
+                }
+                if visited.len() >= MAX_NODES {
+                    tracing::warn!("互动视频节点超过 {} 个，停止遍历", MAX_NODES);
+                    graph.truncated = true;
+                    continue;
+                }
+                visited.insert(choice.edge_id);
+                let next = self
+                    .fetch_stein_edge(aid, graph_version, choice.edge_id)
+                    .await?;
+                queue.push_back((next, choice.cid));
+            }
+            graph.nodes.insert(node.edge_id, node);
+        }
+        Ok(graph)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct SteinEdgeInfo {
+    #[serde(default)]
+    pub title: String,
+    pub edge_id: i64,
+    #[serde(default)]
+    pub edges: SteinEdges,
+    /// 1 表示结局节点
+    #[serde(default)]
+    pub is_leaf: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct SteinEdges {
+    #[serde(default)]
+    pub questions: Vec<SteinQuestion>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct SteinQuestion {
+    #[serde(default)]
+    pub id: i64,
+    #[serde(default)]
+    pub title: String,
+    #[serde(default)]
+    pub choices: Vec<SteinChoiceInfo>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct SteinChoiceInfo {
+    /// 选择后跳转到的节点
+    pub id: i64,
+    pub cid: i64,
+    #[serde(default)]
+    pub option: String,
+    /// 出现该选项需要满足的隐藏变量条件
+    #[serde(default)]
+    pub condition: String,
+    #[serde(default)]
+    pub is_default: i64,
+}
+
+/// 互动视频的剧情图，节点按 edge_id 排序
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct SteinGraph {
+    pub aid: i64,
+    pub graph_version: i64,
+    pub root_edge_id: i64,
+    pub nodes: BTreeMap<i64, SteinNode>,
+    /// 节点数超过上限，剧情图不完整
+    #[serde(default)]
+    pub truncated: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct SteinNode {
+    pub edge_id: i64,
+    pub cid: i64,
+    pub title: String,
+    pub is_leaf: bool,
+    pub choices: Vec<SteinChoice>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct SteinChoice {
+    pub edge_id: i64,
+    pub cid: i64,
+    pub option: String,
+    pub condition: String,
+}
+
+impl SteinNode {
+    fn new(edge: &SteinEdgeInfo, cid: i64) -> SteinNode {
+        let choices = edge
+            .edges
+            .questions
+            .iter()
+            .flat_map(|q| q.choices.iter())
+            .map(|c| SteinChoice {
+                edge_id: c.id,
+                cid: c.cid,
+                option: c.option.clone(),
+                condition: c.condition.clone(),
+            })
+            .collect::<Vec<_>>();
+        SteinNode {
+            edge_id: edge.edge_id,
+            cid,
+            title: edge.title.clone(),
+            is_leaf: edge.is_leaf == 1 || choices.is_empty(),
+            choices,
+        }
+    }
+}
+
+impl SteinGraph {
+    /// 按广度优先的顺序排列的节点，第一个为起始节点
+    pub fn ordered_nodes(&self) -> Vec<&SteinNode> {
+        let mut visited = HashSet::new();
+        let mut queue = VecDeque::from([self.root_edge_id]);
+        let mut ordered = vec![];
+        while let Some(edge_id) = queue.pop_front() {
+            if !visited.insert(edge_id) {
+                continue;
+            }
+            let Some(node) = self.nodes.get(&edge_id) else {
+                continue;
+            };
+            queue.extend(node.choices.iter().map(|c| c.edge_id));
+            ordered.push(node);
+        }
+        ordered
+    }
+
+    /// Graphviz dot 格式的剧情图
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph stein {\n");
+        for node in self.ordered_nodes() {
+            dot.push_str(
+                format!(
+                    "    e{} [label=\"{}\\ncid={}\"{}];\n",
+                    node.edge_id,
+                    escape_dot(&node.title),
+                    node.cid,
+                    if node.is_leaf { ", shape=box" } else { "" }
+                )
+                .as_str(),
+            );
+            for choice in &node.choices {
+                dot.push_str(
+                    format!(
+                        "    e{} -> e{} [label=\"{}\"];\n",
+                        node.edge_id,
+                        choice.edge_id,
+                        escape_dot(&choice.option)
+                    )
+                    .as_str(),
+                );
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+fn escape_dot(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SteinEdgeInfo, SteinGraph, SteinNode};
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn test_stein_graph_to_dot() {
+        let root: SteinEdgeInfo = serde_json::from_str(
+            r#"{"title":"开始","edge_id":1,"is_leaf":0,"edges":{"questions":[{"id":9,"title":"","choices":[
+            {"id":2,"cid":20,"option":"向左","condition":"","is_default":1},
+            {"id":3,"cid":30,"option":"向\"右\"","condition":"$a>=1","is_default":0}]}]}}"#,
+        )
+        .unwrap();
+        let left: SteinEdgeInfo =
+            serde_json::from_str(r#"{"title":"结局","edge_id":2,"is_leaf":1}"#).unwrap();
+        let graph = SteinGraph {
+            aid: 1,
+            graph_version: 1,
+            root_edge_id: 1,
+            nodes: BTreeMap::from([
+                (1, SteinNode::new(&root, 10)),
+                (2, SteinNode::new(&left, 20)),
+            ]),
+            truncated: false,
+        };
+        assert_eq!(graph.nodes[&1].choices[1].condition, "$a>=1");
+        assert!(graph.nodes[&2].is_leaf);
+        assert_eq!(
+            graph
+                .ordered_nodes()
+                .iter()
+                .map(|n| n.cid)
+                .collect::<Vec<_>>(),
+            vec![10, 20]
+        );
+        assert_eq!(
+            graph.to_dot(),
+            "digraph stein {\n    e1 [label=\"开始\\ncid=10\"];\n    e1 -> e2 [label=\"向左\"];\n    e1 -> e3 [label=\"向\\\"右\\\"\"];\n    e2 [label=\"结局\\ncid=20\", shape=box];\n}\n"
+        );
+    }
+}
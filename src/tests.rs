@@ -1,6 +1,4 @@
-use std::{path::Path, sync::Arc};
-#[cfg(feature = "http2")]
-use std::time::Duration;
+use std::path::Path;
 
 pub(crate) fn log_init() {
     let _ = tracing_subscriber::fmt()
@@ -12,50 +10,15 @@ pub(crate) static BBDD: std::sync::LazyLock<crate::BBDD> =
     std::sync::LazyLock::<crate::BBDD>::new(|| bbdd());
 
 fn bbdd() -> crate::BBDD {
-    let ua = ua();
-    let builder = reqwest::Client::builder().redirect(reqwest::redirect::Policy::none());
-    #[cfg(feature = "http2")]
-    let builder = builder
-        .http2_adaptive_window(true)
-        .http2_keep_alive_interval(Some(Duration::from_secs(20)))
-        .http2_keep_alive_timeout(Duration::from_secs(20));
-    #[cfg(feature = "http3")]
-    let builder = builder.http3_prior_knowledge();
-    let agent = builder.build().unwrap();
-    let cookie = cookie();
-    crate::BBDD {
-        agent: Arc::new(agent),
-        ua: ua,
-        cookie: cookie,
-    }
-}
-
-fn ua() -> String {
-    let ua_path = Path::new(env!("CARGO_MANIFEST_DIR"))
-        .join("target")
-        .join("ua.txt");
-    if ua_path.exists() {
-        std::fs::read_to_string(ua_path).unwrap()
-    } else {
-        let ua = crate::util::random_user_agent();
-        std::fs::write(ua_path, &ua).unwrap();
-        ua
-    }
-}
-
-fn cookie() -> String {
-    let cookie_path = Path::new(env!("CARGO_MANIFEST_DIR"))
-        .join("target")
-        .join("login.json");
-    if cookie_path.exists() {
-        let json = std::fs::read_to_string(cookie_path).unwrap();
-        let data: crate::auth::web::WebLoginQRVerifyData = serde_json::from_str(&json).unwrap();
-        crate::util::url_to_cookie(&data.url).unwrap()
-    } else {
-        String::new()
-    }
+    let target = Path::new(env!("CARGO_MANIFEST_DIR")).join("target");
+    crate::BBDD::builder()
+        .ua_file(target.join("ua.txt"))
+        .cookie_source(crate::CookieSource::WebLoginFile(target.join("login.json")))
+        .build()
+        .unwrap()
 }
 
+#[cfg(feature = "stream")]
 pub(crate) fn store_login(data: &crate::auth::web::WebLoginQRVerifyData) -> std::io::Result<()> {
     let login_path = Path::new(env!("CARGO_MANIFEST_DIR"))
         .join("target")
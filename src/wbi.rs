@@ -0,0 +1,170 @@
+use crate::{ApiErrorKind, BBDD, Error, Result};
+use std::time::{Duration, Instant};
+
+const NAV_URL: &str = "https://api.bilibili.com/x/web-interface/nav";
+
+/// WBI 密钥每天更换，超过该时间后重新获取
+const WBI_KEY_TTL: Duration = Duration::from_secs(60 * 60);
+
+pub(crate) struct WbiKey {
+    key: String,
+    fetched: Instant,
+}
+
+impl BBDD {
+    /// 获取 WBI 签名使用的 mixin key，缓存一小时
+    pub async fn wbi_mixin_key(&self) -> Result<String> {
+        if let Some(cached) = self.wbi_key.read().unwrap().as_ref()
+            && cached.fetched.elapsed() < WBI_KEY_TTL
+        {
+            return Ok(cached.key.clone());
+        }
+        // 未登录时 nav 返回 -101，但 data 中仍然包含 wbi_img
+        let nav = self.get_json(NAV_URL, None).await?;
+        let wbi_img = &nav["data"]["wbi_img"];
+        let orig = format!(
+            "{}{}",
+            r_sub_string(wbi_img["img_url"].as_str().unwrap_or_default())?,
+            r_sub_string(wbi_img["sub_url"].as_str().unwrap_or_default())?
+        );
+        let key = mixin_key(orig.as_str())?;
+        *self.wbi_key.write().unwrap() = Some(WbiKey {
+            key: key.clone(),
+            fetched: Instant::now(),
+        });
+        Ok(key)
+    }
+
+    /// 清除缓存的 WBI 密钥，下次签名时重新获取
+    pub fn invalidate_wbi_key(&self) {
+        *self.wbi_key.write().unwrap() = None;
+    }
+
+    /// 使用当前的 WBI 密钥签名参数，返回带 w_rid 的查询字符串
+    pub async fn wbi_sign(&self, params: &[(String, String)]) -> Result<String> {
+        let key = self.wbi_mixin_key().await?;
+        Ok(wbi_sign(params, &key, chrono::Utc::now().timestamp()))
+    }
+
+    /// 请求需要 WBI 签名的接口，签名被拒绝时刷新密钥重试一次
+    pub async fn get_wbi_data<T: for<'de> serde::Deserialize<'de>>(
+        &self,
+        url: &str,
+        params: &[(String, String)],
+    ) -> Result<T> {
+        let signed = format!("{}?{}", url, self.wbi_sign(params).await?);
+        match self.get_data(signed.as_str(), None).await {
+            Err(e) if is_sign_rejected(&e) => {
+                tracing::warn!("WBI 签名被拒绝，刷新密钥后重试: {}", e);
+                self.invalidate_wbi_key();
+                let signed = format!("{}?{}", url, self.wbi_sign(params).await?);
+                self.get_data(signed.as_str(), None).await
+            }
+            result => result,
+        }
+    }
+}
+
+fn is_sign_rejected(e: &Error) -> bool {
+    matches!(
+        e.api_error_kind(),
+        Some(ApiErrorKind::PermissionDenied | ApiErrorKind::RateLimited)
+    )
+}
+
+/// 按照 WBI 规则签名：加入 wts，按参数名排序，去掉值中的 `!'()*`，编码后拼接 mixin key 计算 md5
+pub fn wbi_sign(params: &[(String, String)], mixin_key: &str, wts: i64) -> String {
+    let mut params: Vec<(String, String)> = params
+        .iter()
+        .filter(|(k, _)| k != "wts" && k != "w_rid")
+        .map(|(k, v)| {
+            (
+                k.clone(),
+                v.chars().filter(|c| !"!'()*".contains(*c)).collect(),
+            )
+        })
+        .collect();
+    params.push(("wts".to_string(), wts.to_string()));
+    params.sort_by(|a, b| a.0.cmp(&b.0));
+    let query = params
+        .iter()
+        .map(|(k, v)| format!("{}={}", encode_uri_component(k), encode_uri_component(v)))
+        .collect::<Vec<_>>()
+        .join("&");
+    let sign = hex::encode(md5::compute(format!("{}{}", query, mixin_key)).0);
+    format!("{}&w_rid={}", query, sign)
+}
+
+/// 与 JavaScript 的 encodeURIComponent 相同，空格编码为 %20，十六进制大写
+fn encode_uri_component(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-_.!~*'()".contains(&byte) {
+            out.push(byte as char);
+        } else {
+            out.push_str(format!("%{:02X}", byte).as_str());
+        }
+    }
+    out
+}
+
+fn r_sub_string(url: &str) -> Result<&str> {
+    let last = url
+        .rsplit('/')
+        .next()
+        .ok_or_else(|| Error::StateError("Invalid wbi url".to_string()))?;
+    if let Some((stem, _ext)) = last.rsplit_once('.') {
+        Ok(stem)
+    } else {
+        Err(Error::StateError("Invalid wbi url".to_string()))
+    }
+}
+
+fn mixin_key(orig: &str) -> Result<String> {
+    const MIXIN_KEY_ENC_TAB: [usize; 32] = [
+        46, 47, 18, 2, 53, 8, 23, 32, 15, 50, 10, 31, 58, 3, 45, 35, 27, 43, 5, 49, 33, 9, 42, 19,
+        29, 28, 14, 39, 12, 38, 41, 13,
+    ];
+    let chars: Vec<char> = orig.chars().collect();
+    let mut out = String::with_capacity(32);
+    for idx in MIXIN_KEY_ENC_TAB {
+        let ch = *chars
+            .get(idx)
+            .ok_or_else(|| Error::StateError("Invalid wbi key material".to_string()))?;
+        out.push(ch);
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{mixin_key, r_sub_string, wbi_sign};
+
+    #[test]
+    fn test_r_sub_string_uses_last_dot() {
+        let url = "https://i0.hdslb.com/bfs/wbi/abc.def.png";
+        let stem = r_sub_string(url).unwrap();
+        assert_eq!(stem, "abc.def");
+    }
+
+    #[test]
+    fn test_mixin_key_length() {
+        let orig = "0123456789abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ__";
+        let key = mixin_key(orig).unwrap();
+        assert_eq!(key.len(), 32);
+        assert!(mixin_key("short").is_err());
+    }
+
+    #[test]
+    fn test_wbi_sign() {
+        let params = [("foo", "114"), ("bar", "514"), ("zab", "1919810")]
+            .map(|(k, v)| (k.to_string(), v.to_string()));
+        assert_eq!(
+            wbi_sign(&params, "ea1db124af3c7062474693fa704f4ff8", 1702204169),
+            "bar=514&foo=114&wts=1702204169&zab=1919810&w_rid=8f6f2b5b3d485fe1886cec6a0be8c5d4"
+        );
+        let params =
+            [("keyword", "a b!(c)*'"), ("wts", "1")].map(|(k, v)| (k.to_string(), v.to_string()));
+        assert!(wbi_sign(&params, "key", 2).starts_with("keyword=a%20bc&wts=2&w_rid="));
+    }
+}